@@ -0,0 +1,100 @@
+use std::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use crate::cache_padded::CachePadded;
+
+/// Anderson's array-based queue lock.
+///
+/// At most `size` threads may hold or wait for the lock at the same time.
+pub struct ArrayLock<T> {
+    flags: Vec<CachePadded<AtomicBool>>,
+    tail: CachePadded<AtomicUsize>,
+    mask: usize,
+    data: UnsafeCell<T>,
+}
+
+pub struct ArrayLockGuard<'a, T> {
+    array_lock: &'a ArrayLock<T>,
+    slot: usize,
+}
+
+unsafe impl<T> Sync for ArrayLock<T> {}
+unsafe impl<T> Send for ArrayLock<T> {}
+
+impl<T> ArrayLock<T> {
+    pub fn new(v: T, size: usize) -> Self {
+        assert!(size.is_power_of_two());
+
+        let flags = (0..size)
+            .map(|i| CachePadded::new(AtomicBool::new(i == 0)))
+            .collect();
+
+        Self {
+            flags,
+            tail: CachePadded::new(AtomicUsize::new(0)),
+            mask: size - 1,
+            data: UnsafeCell::new(v),
+        }
+    }
+
+    pub fn lock(&self) -> ArrayLockGuard<'_, T> {
+        let slot = self.tail.fetch_add(1, Ordering::Relaxed) & self.mask;
+        while !self.flags[slot].load(Ordering::Acquire) {
+            std::hint::spin_loop();
+        }
+        self.flags[slot].store(false, Ordering::Relaxed);
+
+        ArrayLockGuard {
+            array_lock: self,
+            slot,
+        }
+    }
+
+    pub fn try_lock(&self) -> Option<ArrayLockGuard<'_, T>> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let slot = tail & self.mask;
+        if !self.flags[slot].load(Ordering::Acquire) {
+            return None;
+        }
+
+        self.tail
+            .compare_exchange(
+                tail,
+                tail.wrapping_add(1),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            )
+            .ok()?;
+        self.flags[slot].store(false, Ordering::Relaxed);
+
+        Some(ArrayLockGuard {
+            array_lock: self,
+            slot,
+        })
+    }
+}
+
+impl<'a, T> Drop for ArrayLockGuard<'a, T> {
+    fn drop(&mut self) {
+        let al = self.array_lock;
+        let next = (self.slot + 1) & al.mask;
+        al.flags[next].store(true, Ordering::Release);
+    }
+}
+
+impl<'a, T> Deref for ArrayLockGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.array_lock.data.get() }
+    }
+}
+
+impl<'a, T> DerefMut for ArrayLockGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.array_lock.data.get() }
+    }
+}
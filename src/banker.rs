@@ -1,4 +1,173 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fmt,
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BankerError {
+    /// The process id is out of range or not registered.
+    InvalidProcess,
+    /// The resource index is out of range.
+    InvalidResource,
+    /// The request would exceed the maximum claim of the process.
+    ExceedsMax,
+    /// Not enough units are free right now.
+    Unavailable,
+    /// Granting the request would leave the system in an unsafe state.
+    Unsafe,
+}
+
+impl fmt::Display for BankerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            BankerError::InvalidProcess => "invalid process",
+            BankerError::InvalidResource => "invalid resource",
+            BankerError::ExceedsMax => "request exceeds the maximum claim",
+            BankerError::Unavailable => "resources are unavailable",
+            BankerError::Unsafe => "request would lead to an unsafe state",
+        };
+        f.write_str(msg)
+    }
+}
+
+impl std::error::Error for BankerError {}
+
+fn is_safe<M: AsRef<[usize]>, A: AsRef<[usize]>>(
+    available: &[usize],
+    max: &[M],
+    allocation: &[A],
+) -> bool {
+    safe_sequence(available, max, allocation).is_some()
+}
+
+/// Returns an order in which every process can obtain its maximum claim and
+/// finish, or `None` if the state is unsafe.
+///
+/// A process that cannot finish yet waits on the first resource it is short
+/// of, in a heap ordered by its need. When a finished process returns its
+/// allocation, only the processes whose need is now covered are looked at
+/// again, and they continue checking from the next resource on since the free
+/// amounts only grow. This takes O(NTH · NRES · log NTH) in the worst case.
+pub fn safe_sequence<M: AsRef<[usize]>, A: AsRef<[usize]>>(
+    available: &[usize],
+    max: &[M],
+    allocation: &[A],
+) -> Option<Vec<usize>> {
+    let need = |i: usize, r: usize| max[i].as_ref()[r] - allocation[i].as_ref()[r];
+    let mut work = available.to_vec();
+
+    // processes whose needs all fit, in the order they finish
+    let mut worklist = Vec::with_capacity(allocation.len());
+    let mut waiting: Vec<BinaryHeap<Reverse<(usize, usize)>>> =
+        (0..work.len()).map(|_| BinaryHeap::new()).collect();
+
+    // the first resource from `from` on that process i is still short of
+    let shortage =
+        |i: usize, from: usize, work: &[usize]| (from..work.len()).find(|&r| need(i, r) > work[r]);
+
+    for i in 0..allocation.len() {
+        match shortage(i, 0, &work) {
+            Some(r) => waiting[r].push(Reverse((need(i, r), i))),
+            None => worklist.push(i),
+        }
+    }
+
+    let mut head = 0;
+    while let Some(&i) = worklist.get(head) {
+        head += 1;
+
+        for (w, a) in work.iter_mut().zip(allocation[i].as_ref()) {
+            *w += a;
+        }
+
+        for r in 0..work.len() {
+            while let Some(&Reverse((n, j))) = waiting[r].peek() {
+                if n > work[r] {
+                    break;
+                }
+                waiting[r].pop();
+                match shortage(j, r + 1, &work) {
+                    Some(r2) => waiting[r2].push(Reverse((need(j, r2), j))),
+                    None => worklist.push(j),
+                }
+            }
+        }
+    }
+
+    if worklist.len() == allocation.len() {
+        Some(worklist)
+    } else {
+        None
+    }
+}
+
+/// Xorshift generator that the tests and the benchmark use to build random
+/// states for `safe_sequence`.
+#[doc(hidden)]
+pub struct XorShift(pub u64);
+
+impl XorShift {
+    /// Returns a number below `n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        let x = &mut self.0;
+        *x ^= *x << 13;
+        *x ^= *x >> 7;
+        *x ^= *x << 17;
+        (*x % n as u64) as usize
+    }
+}
+
+/// The textbook safety check: scan from the first process for one whose need
+/// fits into the free resources and start over after each hit, which costs
+/// O(NTH² · NRES). Kept as a reference for `safe_sequence` in the tests and
+/// the benchmark, not as part of the API.
+#[doc(hidden)]
+pub fn safe_sequence_scan<M: AsRef<[usize]>, A: AsRef<[usize]>>(
+    available: &[usize],
+    max: &[M],
+    allocation: &[A],
+) -> Option<Vec<usize>> {
+    let mut finish = vec![false; allocation.len()];
+    let mut work = available.to_vec();
+    let mut sequence = Vec::with_capacity(allocation.len());
+
+    loop {
+        let mut found = false;
+        let mut num_true = 0;
+        for (i, alc) in allocation.iter().enumerate() {
+            let alc = alc.as_ref();
+            if finish[i] {
+                num_true += 1;
+                continue;
+            }
+
+            let need = max[i].as_ref().iter().zip(alc).map(|(m, a)| m - a);
+            let is_avail = work.iter().zip(need).all(|(w, n)| *w >= n);
+            if is_avail {
+                found = true;
+                finish[i] = true;
+                sequence.push(i);
+                for (w, a) in work.iter_mut().zip(alc) {
+                    *w += *a;
+                }
+                break;
+            }
+        }
+
+        if num_true == allocation.len() {
+            return Some(sequence);
+        }
+
+        if !found {
+            break;
+        }
+    }
+
+    None
+}
 
 struct Resource<const NRES: usize, const NTH: usize> {
     available: [usize; NRES],
@@ -16,65 +185,304 @@ impl<const NRES: usize, const NTH: usize> Resource<NRES, NTH> {
     }
 
     fn is_safe(&self) -> bool {
-        let mut finish = [false; NTH];
-        let mut work = self.available.clone();
+        is_safe(&self.available, &self.max, &self.allocation)
+    }
+
+    fn take(&mut self, id: usize, resource: usize) -> Result<(), BankerError> {
+        if id >= NTH {
+            return Err(BankerError::InvalidProcess);
+        }
+        if resource >= NRES {
+            return Err(BankerError::InvalidResource);
+        }
+        if self.max[id][resource] == self.allocation[id][resource] {
+            return Err(BankerError::ExceedsMax);
+        }
+        if self.available[resource] == 0 {
+            return Err(BankerError::Unavailable);
+        }
+
+        self.allocation[id][resource] += 1;
+        self.available[resource] -= 1;
+
+        if self.is_safe() {
+            Ok(())
+        } else {
+            self.allocation[id][resource] -= 1;
+            self.available[resource] += 1;
+            Err(BankerError::Unsafe)
+        }
+    }
+
+    fn request(&mut self, id: usize, request: &[usize; NRES]) -> Result<(), BankerError> {
+        if id >= NTH {
+            return Err(BankerError::InvalidProcess);
+        }
+        if (0..NRES).any(|r| self.allocation[id][r] + request[r] > self.max[id][r]) {
+            return Err(BankerError::ExceedsMax);
+        }
+        if (0..NRES).any(|r| request[r] > self.available[r]) {
+            return Err(BankerError::Unavailable);
+        }
+
+        let rows = self.allocation[id].iter_mut().zip(&mut self.available);
+        for ((a, avl), n) in rows.zip(request) {
+            *a += n;
+            *avl -= n;
+        }
+
+        if self.is_safe() {
+            Ok(())
+        } else {
+            let rows = self.allocation[id].iter_mut().zip(&mut self.available);
+            for ((a, avl), n) in rows.zip(request) {
+                *a -= n;
+                *avl += n;
+            }
+            Err(BankerError::Unsafe)
+        }
+    }
+
+    fn release(&mut self, id: usize, resource: usize) {
+        if id >= NTH || resource >= NRES || self.allocation[id][resource] == 0 {
+            return;
+        }
+
+        self.allocation[id][resource] -= 1;
+        self.available[resource] += 1;
+    }
+
+    fn release_all(&mut self, id: usize) {
+        if id >= NTH {
+            return;
+        }
+
+        for (avl, a) in self.available.iter_mut().zip(&mut self.allocation[id]) {
+            *avl += *a;
+            *a = 0;
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Banker<const NRES: usize, const NTH: usize> {
+    resource: Arc<Mutex<Resource<NRES, NTH>>>,
+    cond: Arc<Condvar>,
+}
+
+impl<const NRES: usize, const NTH: usize> Banker<NRES, NTH> {
+    /// Returns `None` if a process claims more of a resource than exists in
+    /// total, since such a process could never be guaranteed to finish.
+    pub fn new(available: [usize; NRES], max: [[usize; NRES]; NTH]) -> Option<Self> {
+        if max
+            .iter()
+            .any(|m| m.iter().zip(&available).any(|(m, a)| m > a))
+        {
+            return None;
+        }
+
+        Some(Banker {
+            resource: Arc::new(Mutex::new(Resource::new(available, max))),
+            cond: Arc::new(Condvar::new()),
+        })
+    }
 
+    pub fn take(&self, id: usize, resource: usize) -> Result<(), BankerError> {
+        let mut r = self.resource.lock().unwrap();
+        r.take(id, resource)
+    }
+
+    /// Waits while the resource is unavailable or granting it would be
+    /// unsafe. Any other error is returned at once since waiting cannot help.
+    pub fn take_blocking(&self, id: usize, resource: usize) -> Result<(), BankerError> {
+        let mut r = self.resource.lock().unwrap();
         loop {
-            let mut found = false;
-            let mut num_true = 0;
-            for (i, alc) in self.allocation.iter().enumerate() {
-                if finish[i] {
-                    num_true += 1;
-                    continue;
+            match r.take(id, resource) {
+                Err(BankerError::Unavailable | BankerError::Unsafe) => {
+                    r = self.cond.wait(r).unwrap()
                 }
+                result => return result,
+            }
+        }
+    }
 
-                let need = self.max[i].iter().zip(alc).map(|(m, a)| m - a);
-                let is_avail = work.iter().zip(need).all(|(w, n)| *w >= n);
-                if is_avail {
-                    found = true;
-                    finish[i] = true;
-                    for (w, a) in work.iter_mut().zip(alc) {
-                        *w += *a;
+    /// Like `take_blocking`, but gives up after `timeout` and returns the
+    /// reason of the last refusal.
+    pub fn take_timeout(
+        &self,
+        id: usize,
+        resource: usize,
+        timeout: Duration,
+    ) -> Result<(), BankerError> {
+        let deadline = Instant::now() + timeout;
+        let mut r = self.resource.lock().unwrap();
+        loop {
+            match r.take(id, resource) {
+                Err(e @ (BankerError::Unavailable | BankerError::Unsafe)) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(e);
                     }
-                    break;
+                    r = self.cond.wait_timeout(r, deadline - now).unwrap().0;
                 }
+                result => return result,
             }
+        }
+    }
 
-            if num_true == NTH {
-                return true;
-            }
+    /// Grants every unit in `request` at once, or nothing if that would
+    /// exceed the maximum claim, the available resources or leave the state
+    /// unsafe.
+    pub fn request(&self, id: usize, request: &[usize; NRES]) -> Result<(), BankerError> {
+        let mut r = self.resource.lock().unwrap();
+        r.request(id, request)
+    }
 
-            if !found {
-                break;
-            }
-        }
+    /// Returns an order in which all processes can run to completion.
+    pub fn safe_sequence(&self) -> Vec<usize> {
+        let r = self.resource.lock().unwrap();
+        safe_sequence(&r.available, &r.max, &r.allocation)
+            .expect("banker: state is always kept safe")
+    }
+
+    pub fn release(&self, id: usize, resource: usize) {
+        let mut r = self.resource.lock().unwrap();
+        r.release(id, resource);
+        // whether a waiter became safe depends on its own claim, so wake all
+        self.cond.notify_all();
+    }
 
-        false
+    /// Releases everything `id` holds.
+    pub fn release_all(&self, id: usize) {
+        let mut r = self.resource.lock().unwrap();
+        r.release_all(id);
+        self.cond.notify_all();
     }
+}
 
-    fn take(&mut self, id: usize, resource: usize) -> bool {
-        if id >= NTH
-            || resource >= NRES
-            || self.available[resource] == 0
-            || self.max[id][resource] == self.allocation[id][resource]
+/// Handle of a process registered with a [`DynBanker`]. Slots are reused,
+/// but a handle stays invalid once its process has deregistered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProcessId {
+    slot: usize,
+    generation: u64,
+}
+
+impl ProcessId {
+    /// The row of the process in the banker's tables.
+    pub fn slot(&self) -> usize {
+        self.slot
+    }
+}
+
+struct DynResource {
+    available: Vec<usize>,
+    allocation: Vec<Vec<usize>>,
+    max: Vec<Vec<usize>>,
+    active: Vec<bool>,
+    // bumped whenever a slot is freed, so that old handles no longer match
+    generation: Vec<u64>,
+}
+
+impl DynResource {
+    fn total(&self, resource: usize) -> usize {
+        self.available[resource] + self.allocation.iter().map(|a| a[resource]).sum::<usize>()
+    }
+
+    // returns the slot of id if it is still registered
+    fn slot(&self, id: ProcessId) -> Option<usize> {
+        let is_active = self.active.get(id.slot).copied().unwrap_or(false);
+        (is_active && self.generation[id.slot] == id.generation).then_some(id.slot)
+    }
+
+    fn register(&mut self, max: Vec<usize>) -> Option<ProcessId> {
+        if max.len() != self.available.len()
+            || max.iter().enumerate().any(|(r, m)| *m > self.total(r))
         {
-            return false;
+            return None;
+        }
+
+        let allocation = vec![0; max.len()];
+        let slot = match self.active.iter().position(|a| !a) {
+            Some(slot) => {
+                self.allocation[slot] = allocation;
+                self.max[slot] = max;
+                self.active[slot] = true;
+                slot
+            }
+            None => {
+                self.allocation.push(allocation);
+                self.max.push(max);
+                self.active.push(true);
+                self.generation.push(0);
+                self.active.len() - 1
+            }
+        };
+
+        Some(ProcessId {
+            slot,
+            generation: self.generation[slot],
+        })
+    }
+
+    fn deregister(&mut self, id: ProcessId) {
+        let id = match self.slot(id) {
+            Some(slot) => slot,
+            None => return,
+        };
+
+        for (avl, a) in self.available.iter_mut().zip(&mut self.allocation[id]) {
+            *avl += *a;
+            *a = 0;
+        }
+        // a zeroed row can always finish, so a free slot never affects is_safe
+        self.max[id].iter_mut().for_each(|m| *m = 0);
+        self.active[id] = false;
+        self.generation[id] += 1;
+    }
+
+    fn take(&mut self, id: ProcessId, resource: usize) -> Result<(), BankerError> {
+        let id = self.slot(id).ok_or(BankerError::InvalidProcess)?;
+        if resource >= self.available.len() {
+            return Err(BankerError::InvalidResource);
+        }
+        if self.max[id][resource] == self.allocation[id][resource] {
+            return Err(BankerError::ExceedsMax);
+        }
+        if self.available[resource] == 0 {
+            return Err(BankerError::Unavailable);
         }
 
         self.allocation[id][resource] += 1;
         self.available[resource] -= 1;
 
-        if self.is_safe() {
-            true
+        if is_safe(&self.available, &self.max, &self.allocation) {
+            Ok(())
         } else {
             self.allocation[id][resource] -= 1;
             self.available[resource] += 1;
-            false
+            Err(BankerError::Unsafe)
         }
     }
 
-    fn release(&mut self, id: usize, resource: usize) {
-        if id >= NTH || resource >= NRES || self.allocation[id][resource] == 0 {
+    fn safe_sequence(&self) -> Vec<ProcessId> {
+        safe_sequence(&self.available, &self.max, &self.allocation)
+            .expect("banker: state is always kept safe")
+            .into_iter()
+            .filter(|&slot| self.active[slot])
+            .map(|slot| ProcessId {
+                slot,
+                generation: self.generation[slot],
+            })
+            .collect()
+    }
+
+    fn release(&mut self, id: ProcessId, resource: usize) {
+        let id = match self.slot(id) {
+            Some(slot) => slot,
+            None => return,
+        };
+        if resource >= self.available.len() || self.allocation[id][resource] == 0 {
             return;
         }
 
@@ -83,25 +491,54 @@ impl<const NRES: usize, const NTH: usize> Resource<NRES, NTH> {
     }
 }
 
+/// A banker whose processes come and go at runtime. Each process registers
+/// with its maximum claim and gets a [`ProcessId`]. Its slot is reused after
+/// it deregisters, but the old id is rejected from then on.
 #[derive(Clone)]
-pub struct Banker<const NRES: usize, const NTH: usize> {
-    resource: Arc<Mutex<Resource<NRES, NTH>>>,
+pub struct DynBanker {
+    resource: Arc<Mutex<DynResource>>,
 }
 
-impl<const NRES: usize, const NTH: usize> Banker<NRES, NTH> {
-    pub fn new(available: [usize; NRES], max: [[usize; NRES]; NTH]) -> Self {
-        Banker {
-            resource: Arc::new(Mutex::new(Resource::new(available, max))),
+impl DynBanker {
+    pub fn new(available: Vec<usize>) -> Self {
+        DynBanker {
+            resource: Arc::new(Mutex::new(DynResource {
+                available,
+                allocation: Vec::new(),
+                max: Vec::new(),
+                active: Vec::new(),
+                generation: Vec::new(),
+            })),
         }
     }
 
-    pub fn take(&self, id: usize, resource: usize) -> bool {
+    /// Returns `None` if `max` has the wrong length or claims more of a
+    /// resource than exists in total.
+    pub fn register(&self, max: Vec<usize>) -> Option<ProcessId> {
+        let mut r = self.resource.lock().unwrap();
+        r.register(max)
+    }
+
+    /// Returns everything `id` still holds and frees its id.
+    pub fn deregister(&self, id: ProcessId) {
+        let mut r = self.resource.lock().unwrap();
+        r.deregister(id)
+    }
+
+    pub fn take(&self, id: ProcessId, resource: usize) -> Result<(), BankerError> {
         let mut r = self.resource.lock().unwrap();
         r.take(id, resource)
     }
 
-    pub fn release(&self, id: usize, resource: usize) {
+    pub fn release(&self, id: ProcessId, resource: usize) {
         let mut r = self.resource.lock().unwrap();
         r.release(id, resource)
     }
+
+    /// Returns an order in which all registered processes can run to
+    /// completion.
+    pub fn safe_sequence(&self) -> Vec<ProcessId> {
+        let r = self.resource.lock().unwrap();
+        r.safe_sequence()
+    }
 }
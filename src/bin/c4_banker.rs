@@ -5,13 +5,13 @@ use oreilly_concurrent::banker::Banker;
 const NUM_LOOP: usize = 100000;
 
 fn main() {
-    let banker = Banker::<2, 2>::new([1, 1], [[1, 1], [1, 1]]);
+    let banker = Banker::<2, 2>::new([1, 1], [[1, 1], [1, 1]]).unwrap();
     let banker0 = banker.clone();
 
     let philosopher0 = thread::spawn(move || {
         for _ in 0..NUM_LOOP {
-            while !banker0.take(0, 0) {}
-            while !banker0.take(0, 1) {}
+            banker0.take_blocking(0, 0).unwrap();
+            banker0.take_blocking(0, 1).unwrap();
 
             println!("0: eating");
 
@@ -22,8 +22,8 @@ fn main() {
 
     let philosopher1 = thread::spawn(move || {
         for _ in 0..NUM_LOOP {
-            while !banker.take(1, 1) {}
-            while !banker.take(1, 0) {}
+            banker.take_blocking(1, 1).unwrap();
+            banker.take_blocking(1, 0).unwrap();
 
             println!("1: eating");
 
@@ -0,0 +1,102 @@
+use std::time::Instant;
+
+use oreilly_concurrent::banker::{safe_sequence, safe_sequence_scan, XorShift};
+
+const NUM_LOOP: usize = 20;
+const NUM_THREADS: [usize; 4] = [16, 64, 256, 1024];
+const NUM_RESOURCES: [usize; 3] = [4, 16, 64];
+
+type SafetyCheck = fn(&[usize], &[Vec<usize>], &[Vec<usize>]) -> Option<Vec<usize>>;
+
+struct State {
+    available: Vec<usize>,
+    max: Vec<Vec<usize>>,
+    allocation: Vec<Vec<usize>>,
+}
+
+// process i holds one unit of everything and needs nth - 1 - i more, so the
+// processes can only finish in reverse order
+fn chain(nth: usize, nres: usize) -> State {
+    State {
+        available: vec![0; nres],
+        max: (0..nth).map(|i| vec![nth - i; nres]).collect(),
+        allocation: vec![vec![1; nres]; nth],
+    }
+}
+
+// random claims and allocations with just enough free resources for one
+// random order of the processes to finish, which is what the banker sees
+// right before it grants or refuses a request
+fn random(nth: usize, nres: usize, seed: u64) -> State {
+    let mut rng = XorShift(seed);
+    let mut next = |n: usize| rng.below(n);
+
+    let max: Vec<Vec<_>> = (0..nth)
+        .map(|_| (0..nres).map(|_| next(16)).collect())
+        .collect();
+    let allocation: Vec<Vec<_>> = max
+        .iter()
+        .map(|m| m.iter().map(|&m| next(m + 1)).collect())
+        .collect();
+
+    let mut order: Vec<_> = (0..nth).collect();
+    for i in (1..nth).rev() {
+        order.swap(i, next(i + 1));
+    }
+
+    let mut available = vec![0; nres];
+    let mut released = vec![0; nres];
+    for &i in &order {
+        for r in 0..nres {
+            let need = max[i][r] - allocation[i][r];
+            available[r] = available[r].max(need.saturating_sub(released[r]));
+            released[r] += allocation[i][r];
+        }
+    }
+
+    State {
+        available,
+        max,
+        allocation,
+    }
+}
+
+fn measure(state: &State, f: SafetyCheck) -> (f64, bool) {
+    let start = Instant::now();
+    let mut is_safe = false;
+    for _ in 0..NUM_LOOP {
+        is_safe = f(&state.available, &state.max, &state.allocation).is_some();
+    }
+    let us = start.elapsed().as_secs_f64() * 1e6 / NUM_LOOP as f64;
+    (us, is_safe)
+}
+
+fn report(name: &str, nth: usize, nres: usize, state: &State) {
+    let (scan, safe0) = measure(state, safe_sequence_scan);
+    let (worklist, safe1) = measure(state, safe_sequence);
+    assert!(safe0 && safe1);
+
+    println!(
+        "{:<6} NTH = {:>4}, NRES = {:>2}: scan {:>10.1} us, worklist {:>8.1} us ({:.1}x)",
+        name,
+        nth,
+        nres,
+        scan,
+        worklist,
+        scan / worklist
+    );
+}
+
+fn main() {
+    for &nth in &NUM_THREADS {
+        for &nres in &NUM_RESOURCES {
+            report("chain", nth, nres, &chain(nth, nres));
+            report(
+                "random",
+                nth,
+                nres,
+                &random(nth, nres, 0x2545_f491_4f6c_dd1d),
+            );
+        }
+    }
+}
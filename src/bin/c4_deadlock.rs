@@ -0,0 +1,40 @@
+use std::{thread, time::Duration};
+
+use oreilly_concurrent::deadlock::ResourceManager;
+
+const NUM_LOOP: usize = 1000;
+
+fn philosopher(manager: ResourceManager, id: usize, first: usize, second: usize) {
+    let mut n = 0;
+    while n < NUM_LOOP {
+        manager.acquire(id, first).unwrap();
+        if manager.acquire(id, second).is_err() {
+            // chosen as the victim: everything was taken away, start over
+            println!("{}: aborted", id);
+            continue;
+        }
+
+        println!("{}: eating", id);
+        n += 1;
+
+        manager.release_all(id);
+    }
+}
+
+fn main() {
+    let manager = ResourceManager::new(vec![1, 1]);
+
+    // take the forks in opposite orders and let the detector break deadlocks
+    manager.spawn_detector(Duration::from_millis(10), |cycle| {
+        let victim = *cycle.iter().max().unwrap();
+        println!("deadlock: {:?}, aborting {}", cycle, victim);
+        victim
+    });
+
+    let manager0 = manager.clone();
+    let philosopher0 = thread::spawn(move || philosopher(manager0, 0, 0, 1));
+    let philosopher1 = thread::spawn(move || philosopher(manager, 1, 1, 0));
+
+    philosopher0.join().unwrap();
+    philosopher1.join().unwrap();
+}
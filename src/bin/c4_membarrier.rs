@@ -1,69 +1,10 @@
-use std::{
-    cell::UnsafeCell,
-    ops::{Deref, DerefMut},
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
-    },
-};
+use std::sync::Arc;
+
+use oreilly_concurrent::spin_lock::SpinLock;
 
 const NUM_THREADS: usize = 4;
 const NUM_LOOP: usize = 100000;
 
-struct SpinLock<T> {
-    lock: AtomicBool,
-    data: UnsafeCell<T>,
-}
-
-struct SpinLockGuard<'a, T> {
-    spin_lock: &'a SpinLock<T>,
-}
-
-impl<T> SpinLock<T> {
-    fn new(v: T) -> Self {
-        SpinLock {
-            lock: AtomicBool::new(false),
-            data: UnsafeCell::new(v),
-        }
-    }
-
-    fn lock(&self) -> SpinLockGuard<T> {
-        loop {
-            while self.lock.load(Ordering::Relaxed) {}
-            if let Ok(_) =
-                self.lock
-                    .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
-            {
-                break;
-            }
-        }
-        SpinLockGuard { spin_lock: self }
-    }
-}
-
-unsafe impl<T> Sync for SpinLock<T> {}
-unsafe impl<T> Send for SpinLock<T> {}
-
-impl<'a, T> Drop for SpinLockGuard<'a, T> {
-    fn drop(&mut self) {
-        self.spin_lock.lock.store(false, Ordering::Relaxed)
-    }
-}
-
-impl<'a, T> Deref for SpinLockGuard<'a, T> {
-    type Target = T;
-
-    fn deref(&self) -> &Self::Target {
-        unsafe { &*self.spin_lock.data.get() }
-    }
-}
-
-impl<'a, T> DerefMut for SpinLockGuard<'a, T> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        unsafe { &mut *self.spin_lock.data.get() }
-    }
-}
-
 fn main() {
     let lock = Arc::new(SpinLock::new(0));
     let mut v = Vec::new();
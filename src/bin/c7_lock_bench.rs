@@ -0,0 +1,167 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Barrier,
+    },
+    time::Instant,
+};
+
+use oreilly_concurrent::{
+    array_lock::ArrayLock,
+    cache_padded::{CachePadded, Layout, Packed, Padded},
+    clh_lock::{CLHLock, CLHNode},
+    cohort_lock::{CohortLock, CohortNode},
+    fair_lock::FairLock,
+    mcs_lock::{MCSLock, MCSNode},
+    spin_lock::SpinLock,
+    ticket_lock::TicketLock,
+};
+
+const NUM_LOOP: usize = 100_000;
+const NUM_THREADS: [usize; 4] = [4, 8, 16, 32];
+
+fn run<F>(num_threads: usize, f: F) -> f64
+where
+    F: Fn(usize) + Send + Sync + 'static,
+{
+    let f = Arc::new(f);
+    let barrier = Arc::new(Barrier::new(num_threads + 1));
+    let v: Vec<_> = (0..num_threads)
+        .map(|i| {
+            let f0 = f.clone();
+            let barrier0 = barrier.clone();
+            std::thread::spawn(move || {
+                barrier0.wait();
+                f0(i);
+            })
+        })
+        .collect();
+
+    barrier.wait();
+    let start = Instant::now();
+    for t in v {
+        t.join().unwrap();
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    (num_threads * NUM_LOOP) as f64 / elapsed
+}
+
+fn counters_packed(num_threads: usize) -> f64 {
+    let counters: Arc<Vec<AtomicUsize>> =
+        Arc::new((0..num_threads).map(|_| AtomicUsize::new(0)).collect());
+    run(num_threads, move |i| {
+        for _ in 0..NUM_LOOP {
+            counters[i].fetch_add(1, Ordering::Relaxed);
+        }
+    })
+}
+
+fn counters_padded(num_threads: usize) -> f64 {
+    let counters: Arc<Vec<CachePadded<AtomicUsize>>> = Arc::new(
+        (0..num_threads)
+            .map(|_| CachePadded::new(AtomicUsize::new(0)))
+            .collect(),
+    );
+    run(num_threads, move |i| {
+        for _ in 0..NUM_LOOP {
+            counters[i].fetch_add(1, Ordering::Relaxed);
+        }
+    })
+}
+
+fn mcs_lock<L: Layout + 'static>(num_threads: usize) -> f64 {
+    let lock = MCSLock::<_, L>::with_layout(0);
+    run(num_threads, move |_| {
+        let mut node = MCSNode::with_layout();
+        for _ in 0..NUM_LOOP {
+            let mut r = lock.lock(&mut node);
+            *r += 1;
+        }
+    })
+}
+
+fn clh_lock(num_threads: usize) -> f64 {
+    let lock = CLHLock::new(0);
+    run(num_threads, move |_| {
+        let mut node = CLHNode::new();
+        for _ in 0..NUM_LOOP {
+            let mut r = lock.lock(&mut node);
+            *r += 1;
+        }
+    })
+}
+
+fn cohort_lock(num_threads: usize) -> f64 {
+    let lock = CohortLock::new(0);
+    run(num_threads, move |_| {
+        let mut node = CohortNode::new();
+        for _ in 0..NUM_LOOP {
+            let mut r = lock.lock(&mut node);
+            *r += 1;
+        }
+    })
+}
+
+fn fair_lock<L: Layout + 'static>(num_threads: usize) -> f64 {
+    let lock = FairLock::<_, L>::with_capacity(0, num_threads.next_power_of_two());
+    run(num_threads, move |i| {
+        for _ in 0..NUM_LOOP {
+            let mut r = lock.lock(i);
+            *r += 1;
+        }
+    })
+}
+
+fn spin_lock(num_threads: usize) -> f64 {
+    let lock = SpinLock::new(0);
+    run(num_threads, move |_| {
+        for _ in 0..NUM_LOOP {
+            let mut r = lock.lock();
+            *r += 1;
+        }
+    })
+}
+
+fn ticket_lock(num_threads: usize) -> f64 {
+    let lock = TicketLock::new(0);
+    run(num_threads, move |_| {
+        for _ in 0..NUM_LOOP {
+            let mut r = lock.lock();
+            *r += 1;
+        }
+    })
+}
+
+fn array_lock(num_threads: usize) -> f64 {
+    let lock = ArrayLock::new(0, num_threads.next_power_of_two());
+    run(num_threads, move |_| {
+        for _ in 0..NUM_LOOP {
+            let mut r = lock.lock();
+            *r += 1;
+        }
+    })
+}
+
+fn report(name: &str, num_threads: usize, ops: f64) {
+    println!(
+        "{:<16} threads = {:>2}: {:>12.0} ops/s",
+        name, num_threads, ops
+    );
+}
+
+fn main() {
+    for &n in &NUM_THREADS {
+        report("counters packed", n, counters_packed(n));
+        report("counters padded", n, counters_padded(n));
+        report("SpinLock", n, spin_lock(n));
+        report("TicketLock", n, ticket_lock(n));
+        report("ArrayLock", n, array_lock(n));
+        report("MCSLock packed", n, mcs_lock::<Packed>(n));
+        report("MCSLock padded", n, mcs_lock::<Padded>(n));
+        report("CLHLock", n, clh_lock(n));
+        report("CohortLock", n, cohort_lock(n));
+        report("FairLock packed", n, fair_lock::<Packed>(n));
+        report("FairLock padded", n, fair_lock::<Padded>(n));
+    }
+}
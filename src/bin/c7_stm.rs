@@ -1,15 +1,19 @@
 use core::time;
 use std::sync::Arc;
 
-use oreilly_concurrent::tl2;
+use oreilly_concurrent::{
+    norec,
+    stm::{STMResult, Stm, TVar, Transaction, WriteTransaction},
+    tl2::{self, contention::ExponentialBackoff},
+};
 
 #[macro_export]
 macro_rules! load {
     ($t:ident, $a:expr) => {
-        if let Some(v) = ($t).load($a) {
+        if let Some(v) = ($t).read($a) {
             v
         } else {
-            return tl2::STMResult::Retry;
+            return STMResult::Retry;
         }
     };
 }
@@ -17,67 +21,53 @@ macro_rules! load {
 #[macro_export]
 macro_rules! store {
     ($t:ident, $a:expr, $v:expr) => {
-        $t.store($a, $v)
+        $t.write($a, $v)
     };
 }
 
 const NUM_PHILOSOPHERS: usize = 8;
 
-fn philosopher(stm: Arc<tl2::STM>, n: usize) {
-    let left = 8 * n;
-    let right = 8 * ((n + 1) % NUM_PHILOSOPHERS);
+fn philosopher<S: Stm>(stm: Arc<S>, chopsticks: Arc<Vec<TVar<bool>>>, n: usize) {
+    let left = &chopsticks[n];
+    let right = &chopsticks[(n + 1) % NUM_PHILOSOPHERS];
 
     for _ in 0..500_000 {
-        while !stm
-            .write_tansaction(|tr| {
-                let mut f1 = load!(tr, left);
-                let mut f2 = load!(tr, right);
-                if f1[0] == 0 && f2[0] == 0 {
-                    f1[0] = 1;
-                    f2[0] = 1;
-                    store!(tr, left, f1);
-                    store!(tr, right, f2);
-                    tl2::STMResult::Ok(true)
-                } else {
-                    tl2::STMResult::Ok(false)
-                }
-            })
-            .unwrap()
-        {}
-
-        stm.write_tansaction(|tr| {
-            let mut f1 = load!(tr, left);
-            let mut f2 = load!(tr, right);
-            f1[0] = 0;
-            f2[0] = 0;
-            store!(tr, left, f1);
-            store!(tr, right, f2);
-            tl2::STMResult::Ok(())
+        stm.write_transaction(|tr| {
+            let f1 = load!(tr, left);
+            let f2 = load!(tr, right);
+            if f1 || f2 {
+                return STMResult::Retry;
+            }
+            store!(tr, left, true);
+            store!(tr, right, true);
+            STMResult::Ok(())
+        })
+        .unwrap();
+
+        stm.write_transaction(|tr| {
+            store!(tr, left, false);
+            store!(tr, right, false);
+            STMResult::Ok(())
         });
     }
 }
 
-fn observer(stm: Arc<tl2::STM>) {
+fn observer<S: Stm>(stm: Arc<S>, chopsticks: Arc<Vec<TVar<bool>>>) {
     for _ in 0..10000 {
         let chopsticks = stm
             .read_transaction(|tr| {
-                let mut v = [0; NUM_PHILOSOPHERS];
-                for i in 0..NUM_PHILOSOPHERS {
-                    v[i] = load!(tr, 8 * i)[0];
+                let mut v = [false; NUM_PHILOSOPHERS];
+                for (dst, c) in v.iter_mut().zip(chopsticks.iter()) {
+                    *dst = load!(tr, c);
                 }
 
-                tl2::STMResult::Ok(v)
+                STMResult::Ok(v)
             })
             .unwrap();
 
         println!("{:?}", chopsticks);
 
-        let mut n = 0;
-        for c in &chopsticks {
-            if *c == 1 {
-                n += 1;
-            }
-        }
+        let n = chopsticks.iter().filter(|c| **c).count();
 
         if n & 1 != 0 {
             panic!("inconsistent");
@@ -88,16 +78,19 @@ fn observer(stm: Arc<tl2::STM>) {
     }
 }
 
-fn main() {
-    let stm = Arc::new(tl2::STM::new());
+fn dining<S: Stm + 'static>(stm: S) {
+    let stm = Arc::new(stm);
+    let chopsticks: Arc<Vec<_>> =
+        Arc::new((0..NUM_PHILOSOPHERS).map(|_| stm.alloc(false)).collect());
     let v: Vec<_> = (0..NUM_PHILOSOPHERS)
         .map(|i| {
             let s = stm.clone();
-            std::thread::spawn(move || philosopher(s, i))
+            let c = chopsticks.clone();
+            std::thread::spawn(move || philosopher(s, c, i))
         })
         .collect();
 
-    let obs = std::thread::spawn(move || observer(stm));
+    let obs = std::thread::spawn(move || observer(stm, chopsticks));
 
     for th in v {
         th.join().unwrap();
@@ -105,3 +98,16 @@ fn main() {
 
     obs.join().unwrap();
 }
+
+fn main() {
+    match std::env::args().nth(1).as_deref() {
+        None | Some("tl2") => {
+            dining(tl2::STM::new().with_contention_manager(ExponentialBackoff::default()))
+        }
+        Some("norec") => dining(norec::STM::new()),
+        Some(name) => {
+            eprintln!("unknown STM backend: {} (expected tl2 or norec)", name);
+            std::process::exit(1);
+        }
+    }
+}
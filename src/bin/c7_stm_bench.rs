@@ -0,0 +1,97 @@
+use std::{
+    sync::{Arc, Barrier},
+    time::Instant,
+};
+
+use oreilly_concurrent::{
+    norec,
+    stm::{STMResult, Stm, TVar, Transaction, WriteTransaction},
+    tl2::{self, contention::ExponentialBackoff},
+};
+
+const NUM_LOOP: usize = 20_000;
+const NUM_VARS: usize = 32;
+const NUM_READS: usize = 8;
+const WRITE_EVERY: usize = 10;
+const NUM_THREADS: [usize; 4] = [1, 2, 4, 8];
+
+macro_rules! load {
+    ($t:ident, $a:expr) => {
+        match $t.read($a) {
+            Some(v) => v,
+            None => return STMResult::Retry,
+        }
+    };
+}
+
+// 1 in WRITE_EVERY transactions increments one variable, the rest read NUM_READS of them
+fn read_mostly<S: Stm + 'static>(stm: S, num_threads: usize) -> f64 {
+    let stm = Arc::new(stm);
+    let vars: Arc<Vec<TVar<u64>>> = Arc::new((0..NUM_VARS).map(|_| stm.alloc(0)).collect());
+    let barrier = Arc::new(Barrier::new(num_threads + 1));
+
+    let v: Vec<_> = (0..num_threads)
+        .map(|i| {
+            let stm0 = stm.clone();
+            let vars0 = vars.clone();
+            let barrier0 = barrier.clone();
+            std::thread::spawn(move || {
+                barrier0.wait();
+                for j in 0..NUM_LOOP {
+                    let base = (i * 7 + j) % NUM_VARS;
+                    if j % WRITE_EVERY == 0 {
+                        let var = &vars0[base];
+                        stm0.write_transaction(|tr| {
+                            let n = load!(tr, var);
+                            tr.write(var, n + 1);
+                            STMResult::Ok(())
+                        });
+                    } else {
+                        stm0.read_transaction(|tr| {
+                            let mut sum = 0;
+                            for k in 0..NUM_READS {
+                                sum += load!(tr, &vars0[(base + k) % NUM_VARS]);
+                            }
+                            STMResult::Ok(sum)
+                        });
+                    }
+                }
+            })
+        })
+        .collect();
+
+    barrier.wait();
+    let start = Instant::now();
+    for t in v {
+        t.join().unwrap();
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    let total = stm
+        .read_transaction(|tr| {
+            let mut sum = 0;
+            for var in vars.iter() {
+                sum += load!(tr, var);
+            }
+            STMResult::Ok(sum)
+        })
+        .unwrap();
+    assert_eq!(total as usize, num_threads * NUM_LOOP.div_ceil(WRITE_EVERY));
+
+    (num_threads * NUM_LOOP) as f64 / elapsed
+}
+
+fn report(name: &str, num_threads: usize, ops: f64) {
+    println!(
+        "{:<8} threads = {:>2}: {:>12.0} transactions/s",
+        name, num_threads, ops
+    );
+}
+
+fn main() {
+    for &n in &NUM_THREADS {
+        let stm = tl2::STM::new().with_contention_manager(ExponentialBackoff::default());
+        report("TL2", n, read_mostly(stm, n));
+        report("NOrec", n, read_mostly(norec::STM::new(), n));
+    }
+}
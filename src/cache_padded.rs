@@ -0,0 +1,87 @@
+use std::ops::{Deref, DerefMut};
+
+#[cfg_attr(any(target_arch = "x86_64", target_arch = "aarch64"), repr(align(128)))]
+#[cfg_attr(
+    not(any(target_arch = "x86_64", target_arch = "aarch64")),
+    repr(align(64))
+)]
+#[derive(Default)]
+pub struct CachePadded<T> {
+    value: T,
+}
+
+impl<T> CachePadded<T> {
+    pub const fn new(value: T) -> Self {
+        Self { value }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for CachePadded<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.value
+    }
+}
+
+/// A value stored without padding, so neighbouring fields may share a cache
+/// line with it.
+#[repr(transparent)]
+#[derive(Default)]
+pub struct Unpadded<T> {
+    value: T,
+}
+
+impl<T> Unpadded<T> {
+    pub const fn new(value: T) -> Self {
+        Self { value }
+    }
+}
+
+impl<T> Deref for Unpadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+/// Selects how a lock lays out the atomics its threads spin on.
+pub trait Layout {
+    type Slot<T>: Deref<Target = T>;
+
+    fn slot<T>(value: T) -> Self::Slot<T>;
+}
+
+/// Every spin variable on its own cache line.
+pub struct Padded;
+
+/// Spin variables packed next to each other, only useful to measure the cost
+/// of false sharing.
+pub struct Packed;
+
+impl Layout for Padded {
+    type Slot<T> = CachePadded<T>;
+
+    fn slot<T>(value: T) -> Self::Slot<T> {
+        CachePadded::new(value)
+    }
+}
+
+impl Layout for Packed {
+    type Slot<T> = Unpadded<T>;
+
+    fn slot<T>(value: T) -> Self::Slot<T> {
+        Unpadded::new(value)
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+};
+
+use crate::cache_padded::CachePadded;
+
+struct QNode {
+    locked: CachePadded<AtomicBool>,
+}
+
+impl QNode {
+    fn alloc(locked: bool) -> *mut QNode {
+        Box::into_raw(Box::new(QNode {
+            locked: CachePadded::new(AtomicBool::new(locked)),
+        }))
+    }
+}
+
+pub struct CLHLock<T> {
+    last: CachePadded<AtomicPtr<QNode>>,
+    data: UnsafeCell<T>,
+}
+
+pub struct CLHNode<T> {
+    qnode: *mut QNode,
+    _marker: PhantomData<T>,
+}
+
+pub struct CLHLockGuard<'a, T> {
+    node: &'a mut CLHNode<T>,
+    prev: *mut QNode,
+    clh_lock: &'a CLHLock<T>,
+}
+
+unsafe impl<T> Sync for CLHLock<T> {}
+unsafe impl<T> Send for CLHLock<T> {}
+unsafe impl<T> Send for CLHNode<T> {}
+
+impl<T> CLHNode<T> {
+    pub fn new() -> Self {
+        Self {
+            qnode: QNode::alloc(false),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Default for CLHNode<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for CLHNode<T> {
+    fn drop(&mut self) {
+        unsafe { drop(Box::from_raw(self.qnode)) };
+    }
+}
+
+impl<'a, T> Deref for CLHLockGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.clh_lock.data.get() }
+    }
+}
+
+impl<'a, T> DerefMut for CLHLockGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.clh_lock.data.get() }
+    }
+}
+
+impl<T> CLHLock<T> {
+    pub fn new(v: T) -> Self {
+        Self {
+            last: CachePadded::new(AtomicPtr::new(QNode::alloc(false))),
+            data: UnsafeCell::new(v),
+        }
+    }
+
+    pub fn lock<'a>(&'a self, node: &'a mut CLHNode<T>) -> CLHLockGuard<'a, T> {
+        let ptr = node.qnode;
+        unsafe { (*ptr).locked.store(true, Ordering::Relaxed) };
+
+        let prev = self.last.swap(ptr, Ordering::AcqRel);
+
+        while unsafe { (*prev).locked.load(Ordering::Acquire) } {
+            std::hint::spin_loop();
+        }
+
+        CLHLockGuard {
+            node,
+            prev,
+            clh_lock: self,
+        }
+    }
+}
+
+impl<T> Drop for CLHLock<T> {
+    fn drop(&mut self) {
+        unsafe { drop(Box::from_raw(*self.last.get_mut())) };
+    }
+}
+
+impl<'a, T> Drop for CLHLockGuard<'a, T> {
+    fn drop(&mut self) {
+        let ptr = self.node.qnode;
+        unsafe { (*ptr).locked.store(false, Ordering::Release) };
+
+        // our own node now belongs to the successor; recycle the predecessor's
+        self.node.qnode = self.prev;
+    }
+}
@@ -0,0 +1,146 @@
+use std::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    cache_padded::CachePadded,
+    mcs_lock::{MCSLock, MCSLockGuard, MCSNode},
+    numa::Topology,
+};
+
+pub const DEFAULT_BOUND: usize = 64;
+
+pub struct CohortLock<T> {
+    next: CachePadded<AtomicUsize>,
+    serving: CachePadded<AtomicUsize>,
+    locals: Vec<MCSLock<Cohort>>,
+    topology: Topology,
+    bound: usize,
+    data: UnsafeCell<T>,
+}
+
+struct Cohort {
+    global_held: bool,
+    passes: usize,
+}
+
+pub struct CohortNode {
+    node: MCSNode<Cohort>,
+}
+
+pub struct CohortLockGuard<'a, T> {
+    local: MCSLockGuard<'a, Cohort>,
+    cohort_lock: &'a CohortLock<T>,
+}
+
+unsafe impl<T> Sync for CohortLock<T> {}
+unsafe impl<T> Send for CohortLock<T> {}
+
+impl CohortNode {
+    pub fn new() -> Self {
+        Self {
+            node: MCSNode::new(),
+        }
+    }
+}
+
+impl Default for CohortNode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> CohortLock<T> {
+    pub fn new(v: T) -> Self {
+        Self::with_topology(v, Topology::detect(), DEFAULT_BOUND)
+    }
+
+    pub fn with_bound(v: T, bound: usize) -> Self {
+        Self::with_topology(v, Topology::detect(), bound)
+    }
+
+    pub fn with_topology(v: T, topology: Topology, bound: usize) -> Self {
+        assert!(bound > 0);
+
+        let locals = (0..topology.num_nodes())
+            .map(|_| {
+                MCSLock::new(Cohort {
+                    global_held: false,
+                    passes: 0,
+                })
+            })
+            .collect();
+
+        Self {
+            next: CachePadded::new(AtomicUsize::new(0)),
+            serving: CachePadded::new(AtomicUsize::new(0)),
+            locals,
+            topology,
+            bound,
+            data: UnsafeCell::new(v),
+        }
+    }
+
+    pub fn topology(&self) -> &Topology {
+        &self.topology
+    }
+
+    pub fn lock<'a>(&'a self, node: &'a mut CohortNode) -> CohortLockGuard<'a, T> {
+        self.lock_on(self.topology.current_node(), node)
+    }
+
+    pub fn lock_on<'a>(
+        &'a self,
+        numa_node: usize,
+        node: &'a mut CohortNode,
+    ) -> CohortLockGuard<'a, T> {
+        let mut local = self.locals[numa_node].lock(&mut node.node);
+
+        if !local.global_held {
+            let ticket = self.next.fetch_add(1, Ordering::Relaxed);
+            while self.serving.load(Ordering::Acquire) != ticket {
+                std::hint::spin_loop();
+            }
+            local.global_held = true;
+            local.passes = 0;
+        }
+
+        CohortLockGuard {
+            local,
+            cohort_lock: self,
+        }
+    }
+}
+
+impl<'a, T> Drop for CohortLockGuard<'a, T> {
+    fn drop(&mut self) {
+        let cl = self.cohort_lock;
+
+        if self.local.has_successor() && self.local.passes < cl.bound {
+            self.local.passes += 1;
+            return;
+        }
+
+        self.local.global_held = false;
+        self.local.passes = 0;
+
+        let next = cl.serving.load(Ordering::Relaxed).wrapping_add(1);
+        cl.serving.store(next, Ordering::Release);
+    }
+}
+
+impl<'a, T> Deref for CohortLockGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.cohort_lock.data.get() }
+    }
+}
+
+impl<'a, T> DerefMut for CohortLockGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.cohort_lock.data.get() }
+    }
+}
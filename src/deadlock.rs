@@ -0,0 +1,310 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, Condvar, Mutex, Weak},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcquireError {
+    /// The resource does not exist or has no units at all.
+    InvalidResource,
+    /// The process was chosen as a victim by `ResourceManager::abort`.
+    Aborted,
+}
+
+impl fmt::Display for AcquireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            AcquireError::InvalidResource => "invalid resource",
+            AcquireError::Aborted => "aborted to resolve a deadlock",
+        };
+        f.write_str(msg)
+    }
+}
+
+impl std::error::Error for AcquireError {}
+
+#[derive(Default)]
+struct Process {
+    held: Vec<usize>,
+    waiting: Option<usize>,
+    aborted: bool,
+}
+
+struct State {
+    available: Vec<usize>,
+    procs: BTreeMap<usize, Process>,
+}
+
+impl State {
+    fn process(&mut self, id: usize) -> &mut Process {
+        let nres = self.available.len();
+        self.procs.entry(id).or_insert_with(|| Process {
+            held: vec![0; nres],
+            ..Default::default()
+        })
+    }
+
+    // processes that can never proceed: repeatedly let every process whose
+    // request can be met finish and return what it holds
+    fn deadlocked(&self) -> Vec<usize> {
+        let mut work = self.available.clone();
+        let mut finish: BTreeMap<usize, bool> = self.procs.keys().map(|&id| (id, false)).collect();
+
+        loop {
+            let mut found = false;
+            for (id, p) in &self.procs {
+                if finish[id] || p.waiting.is_some_and(|r| work[r] == 0) {
+                    continue;
+                }
+
+                found = true;
+                finish.insert(*id, true);
+                for (w, h) in work.iter_mut().zip(&p.held) {
+                    *w += h;
+                }
+            }
+
+            if !found {
+                break;
+            }
+        }
+
+        finish
+            .into_iter()
+            .filter_map(|(id, f)| if f { None } else { Some(id) })
+            .collect()
+    }
+
+    // includes id itself if it already holds a unit of what it waits for
+    fn waits_for(&self, id: usize) -> impl Iterator<Item = usize> + '_ {
+        let waiting = self.procs[&id].waiting;
+        self.procs.iter().filter_map(move |(&j, p)| match waiting {
+            Some(r) if p.held[r] > 0 => Some(j),
+            _ => None,
+        })
+    }
+
+    fn supply(&self, resource: usize) -> usize {
+        self.available[resource] + self.procs.values().map(|p| p.held[resource]).sum::<usize>()
+    }
+
+    // every deadlocked process waits for a resource whose units are all held
+    // by deadlocked processes, possibly itself, and acquire never waits for a
+    // resource without units, so following such edges from any of them must
+    // run into a cycle
+    fn find_cycle(&self) -> Option<Vec<usize>> {
+        let deadlocked = self.deadlocked();
+        let mut path = vec![*deadlocked.first()?];
+
+        loop {
+            let last = *path.last().unwrap();
+            let next = self
+                .waits_for(last)
+                .find(|j| deadlocked.binary_search(j).is_ok())
+                .expect("deadlock: a deadlocked process waits for a deadlocked one");
+
+            if let Some(pos) = path.iter().position(|&p| p == next) {
+                return Some(path.split_off(pos));
+            }
+            path.push(next);
+        }
+    }
+
+    fn release_all(&mut self, id: usize) {
+        if let Some(p) = self.procs.get_mut(&id) {
+            for (avl, h) in self.available.iter_mut().zip(&mut p.held) {
+                *avl += *h;
+                *h = 0;
+            }
+            self.remove_if_idle(id);
+        }
+    }
+
+    // forgets a process that holds nothing and is not blocked, so that ids
+    // which are never used again do not pile up
+    fn remove_if_idle(&mut self, id: usize) {
+        let is_idle = self
+            .procs
+            .get(&id)
+            .is_some_and(|p| p.waiting.is_none() && !p.aborted && p.held.iter().all(|h| *h == 0));
+        if is_idle {
+            self.procs.remove(&id);
+        }
+    }
+
+    fn abort(&mut self, id: usize) -> bool {
+        match self.procs.get_mut(&id) {
+            Some(p) if p.waiting.is_some() => {
+                p.waiting = None;
+                p.aborted = true;
+            }
+            _ => return false,
+        }
+        self.release_all(id);
+        true
+    }
+}
+
+struct Inner {
+    state: Mutex<State>,
+    cond: Condvar,
+}
+
+/// Hands out resources without requiring maximum claims up front and detects
+/// deadlocks after the fact instead of avoiding them. Processes are
+/// identified by arbitrary ids and are tracked from their first `acquire`.
+#[derive(Clone)]
+pub struct ResourceManager {
+    inner: Arc<Inner>,
+}
+
+impl ResourceManager {
+    pub fn new(available: Vec<usize>) -> Self {
+        ResourceManager {
+            inner: Arc::new(Inner {
+                state: Mutex::new(State {
+                    available,
+                    procs: BTreeMap::new(),
+                }),
+                cond: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Takes one unit of `resource`, waiting until one is free. Fails with
+    /// `Aborted` if `id` is aborted while waiting.
+    pub fn acquire(&self, id: usize, resource: usize) -> Result<(), AcquireError> {
+        let mut st = self.inner.state.lock().unwrap();
+        if resource >= st.available.len() || st.supply(resource) == 0 {
+            return Err(AcquireError::InvalidResource);
+        }
+
+        loop {
+            let p = st.process(id);
+            if p.aborted {
+                p.aborted = false;
+                st.remove_if_idle(id);
+                return Err(AcquireError::Aborted);
+            }
+
+            if st.available[resource] > 0 {
+                st.available[resource] -= 1;
+                let p = st.process(id);
+                p.held[resource] += 1;
+                p.waiting = None;
+                return Ok(());
+            }
+
+            st.process(id).waiting = Some(resource);
+            st = self.inner.cond.wait(st).unwrap();
+        }
+    }
+
+    pub fn try_acquire(&self, id: usize, resource: usize) -> bool {
+        let mut st = self.inner.state.lock().unwrap();
+        if resource >= st.available.len() || st.available[resource] == 0 {
+            return false;
+        }
+
+        st.available[resource] -= 1;
+        st.process(id).held[resource] += 1;
+        true
+    }
+
+    pub fn release(&self, id: usize, resource: usize) {
+        let mut st = self.inner.state.lock().unwrap();
+        if let Some(p) = st.procs.get_mut(&id) {
+            if p.held.get(resource).is_some_and(|h| *h > 0) {
+                p.held[resource] -= 1;
+                st.available[resource] += 1;
+                st.remove_if_idle(id);
+                self.inner.cond.notify_all();
+            }
+        }
+    }
+
+    pub fn release_all(&self, id: usize) {
+        let mut st = self.inner.state.lock().unwrap();
+        st.release_all(id);
+        self.inner.cond.notify_all();
+    }
+
+    /// Makes `id`, which must be blocked in `acquire`, give up everything it
+    /// holds; that call then returns `AcquireError::Aborted`. Returns `false`
+    /// and does nothing if `id` is not waiting, since a running process
+    /// would keep using what it holds.
+    pub fn abort(&self, id: usize) -> bool {
+        let mut st = self.inner.state.lock().unwrap();
+        let is_aborted = st.abort(id);
+        if is_aborted {
+            self.inner.cond.notify_all();
+        }
+        is_aborted
+    }
+
+    /// Finds a deadlock cycle like `find_cycle` and aborts the process
+    /// `victim` picks from it, all under one lock, so the cycle cannot
+    /// dissolve in between. Returns the victim, or `None` if there is no
+    /// deadlock.
+    pub fn resolve(&self, victim: impl FnOnce(&[usize]) -> usize) -> Option<usize> {
+        let mut st = self.inner.state.lock().unwrap();
+        let cycle = st.find_cycle()?;
+        let id = victim(&cycle);
+        assert!(
+            cycle.contains(&id),
+            "deadlock: the victim is not in the cycle"
+        );
+
+        // a deadlocked process is always blocked in acquire
+        assert!(st.abort(id));
+        self.inner.cond.notify_all();
+        Some(id)
+    }
+
+    /// Edges `(i, j)` of the wait-for graph: `i` is blocked on a resource
+    /// of which `j` holds a unit. `j` is `i` itself if `i` asked for more
+    /// units of a resource it already holds.
+    pub fn wait_for_graph(&self) -> Vec<(usize, usize)> {
+        let st = self.inner.state.lock().unwrap();
+        st.procs
+            .keys()
+            .flat_map(|&i| st.waits_for(i).map(move |j| (i, j)))
+            .collect()
+    }
+
+    /// Returns the processes that cannot proceed no matter what the others
+    /// release, in ascending order.
+    pub fn detect(&self) -> Vec<usize> {
+        let st = self.inner.state.lock().unwrap();
+        st.deadlocked()
+    }
+
+    /// Returns one cycle of the wait-for graph among the deadlocked
+    /// processes, in the order they wait for each other. Aborting any of them
+    /// breaks this cycle.
+    pub fn find_cycle(&self) -> Option<Vec<usize>> {
+        let st = self.inner.state.lock().unwrap();
+        st.find_cycle()
+    }
+
+    /// Checks for a deadlock every `period` and breaks each cycle found with
+    /// `resolve`, aborting the process `victim` picks. The thread exits once
+    /// every handle to the manager is dropped.
+    pub fn spawn_detector<F>(&self, period: Duration, victim: F) -> JoinHandle<()>
+    where
+        F: Fn(&[usize]) -> usize + Send + 'static,
+    {
+        let inner: Weak<Inner> = Arc::downgrade(&self.inner);
+        thread::spawn(move || loop {
+            thread::sleep(period);
+            let manager = match inner.upgrade() {
+                Some(inner) => ResourceManager { inner },
+                None => return,
+            };
+            manager.resolve(&victim);
+        })
+    }
+}
@@ -2,36 +2,55 @@ use std::cell::UnsafeCell;
 use std::ops::{Deref, DerefMut};
 use std::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering};
 
-pub const NUM_LOCK: usize = 8;
+use crate::cache_padded::{Layout, Padded};
 
-const MASK: usize = NUM_LOCK - 1;
+pub const NUM_LOCK: usize = 8;
 
-pub struct FairLock<T> {
-    waiting: Vec<AtomicBool>,
-    lock: AtomicBool,
-    turn: AtomicUsize,
+/// `L` selects whether each thread's `waiting` flag and the shared lock
+/// state sit on their own cache lines (the default) or are packed together.
+pub struct FairLock<T, L: Layout = Padded> {
+    waiting: Vec<L::Slot<AtomicBool>>,
+    mask: usize,
+    lock: L::Slot<AtomicBool>,
+    turn: L::Slot<AtomicUsize>,
     data: UnsafeCell<T>,
 }
 
-pub struct FairLockGuard<'a, T> {
-    fair_lock: &'a FairLock<T>,
+pub struct FairLockGuard<'a, T, L: Layout = Padded> {
+    fair_lock: &'a FairLock<T, L>,
     idx: usize,
 }
 
 impl<T> FairLock<T> {
     pub fn new(v: T) -> Self {
-        let waiting = (0..NUM_LOCK).map(|_| AtomicBool::new(false)).collect();
+        Self::with_layout(v)
+    }
+}
+
+impl<T, L: Layout> FairLock<T, L> {
+    pub fn with_layout(v: T) -> Self {
+        Self::with_capacity(v, NUM_LOCK)
+    }
+
+    /// A lock for thread indices `0..capacity`, which must be a power of two.
+    pub fn with_capacity(v: T, capacity: usize) -> Self {
+        assert!(capacity.is_power_of_two());
+
+        let waiting = (0..capacity)
+            .map(|_| L::slot(AtomicBool::new(false)))
+            .collect();
 
         Self {
             waiting,
-            lock: AtomicBool::new(false),
-            turn: AtomicUsize::new(0),
+            mask: capacity - 1,
+            lock: L::slot(AtomicBool::new(false)),
+            turn: L::slot(AtomicUsize::new(0)),
             data: UnsafeCell::new(v),
         }
     }
 
-    pub fn lock(&self, idx: usize) -> FairLockGuard<T> {
-        assert!(idx < NUM_LOCK);
+    pub fn lock(&self, idx: usize) -> FairLockGuard<'_, T, L> {
+        assert!(idx < self.waiting.len());
 
         self.waiting[idx].store(true, Ordering::Relaxed);
         loop {
@@ -57,7 +76,7 @@ impl<T> FairLock<T> {
     }
 }
 
-impl<'a, T> Drop for FairLockGuard<'a, T> {
+impl<'a, T, L: Layout> Drop for FairLockGuard<'a, T, L> {
     fn drop(&mut self) {
         let fl = self.fair_lock;
 
@@ -65,7 +84,7 @@ impl<'a, T> Drop for FairLockGuard<'a, T> {
 
         let turn = fl.turn.load(Ordering::Relaxed);
         let next = if turn == self.idx {
-            (turn + 1) & MASK
+            (turn + 1) & fl.mask
         } else {
             turn
         };
@@ -74,16 +93,16 @@ impl<'a, T> Drop for FairLockGuard<'a, T> {
             fl.turn.store(next, Ordering::Relaxed);
             fl.waiting[next].store(false, Ordering::Release);
         } else {
-            fl.turn.store((next + 1) & MASK, Ordering::Relaxed);
+            fl.turn.store((next + 1) & fl.mask, Ordering::Relaxed);
             fl.lock.store(false, Ordering::Release);
         }
     }
 }
 
-unsafe impl<T> Sync for FairLock<T> {}
-unsafe impl<T> Send for FairLock<T> {}
+unsafe impl<T, L: Layout> Sync for FairLock<T, L> {}
+unsafe impl<T, L: Layout> Send for FairLock<T, L> {}
 
-impl<'a, T> Deref for FairLockGuard<'a, T> {
+impl<'a, T, L: Layout> Deref for FairLockGuard<'a, T, L> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
@@ -91,7 +110,7 @@ impl<'a, T> Deref for FairLockGuard<'a, T> {
     }
 }
 
-impl<'a, T> DerefMut for FairLockGuard<'a, T> {
+impl<'a, T, L: Layout> DerefMut for FairLockGuard<'a, T, L> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         unsafe { &mut *self.fair_lock.data.get() }
     }
@@ -1,7 +1,17 @@
+pub mod array_lock;
 pub mod banker;
+pub mod cache_padded;
 pub mod channel;
+pub mod clh_lock;
+pub mod cohort_lock;
+pub mod deadlock;
 pub mod fair_lock;
 pub mod mcs_lock;
+pub mod norec;
+pub mod numa;
 pub mod scheduling;
 pub mod semaphore;
+pub mod spin_lock;
+pub mod stm;
+pub mod ticket_lock;
 pub mod tl2;
@@ -1,35 +1,70 @@
 use std::{
     cell::UnsafeCell,
+    marker::PhantomData,
     ops::{Deref, DerefMut},
     ptr::null_mut,
-    sync::atomic::{fence, AtomicBool, AtomicPtr, Ordering},
+    sync::atomic::{fence, AtomicPtr, AtomicU8, Ordering},
+    time::{Duration, Instant},
 };
 
-pub struct MCSLock<T> {
-    last: AtomicPtr<MCSNode<T>>,
+use crate::cache_padded::{Layout, Padded};
+
+const WAITING: u8 = 0;
+const GRANTED: u8 = 1;
+const ABANDONED: u8 = 2;
+
+struct QNode<L: Layout> {
+    next: L::Slot<AtomicPtr<QNode<L>>>,
+    state: L::Slot<AtomicU8>,
+}
+
+impl<L: Layout> QNode<L> {
+    fn alloc() -> *mut QNode<L> {
+        Box::into_raw(Box::new(QNode {
+            next: L::slot(AtomicPtr::new(null_mut())),
+            state: L::slot(AtomicU8::new(WAITING)),
+        }))
+    }
+}
+
+/// MCS lock. `L` selects whether the queue tail and the per-thread spin
+/// state are kept on separate cache lines (the default) or packed together.
+pub struct MCSLock<T, L: Layout = Padded> {
+    last: L::Slot<AtomicPtr<QNode<L>>>,
     data: UnsafeCell<T>,
 }
 
-pub struct MCSNode<T> {
-    next: AtomicPtr<MCSNode<T>>,
-    locked: AtomicBool,
+pub struct MCSNode<T, L: Layout = Padded> {
+    qnode: *mut QNode<L>,
+    _marker: PhantomData<T>,
 }
 
-pub struct MCSLockGuard<'a, T> {
-    node: &'a mut MCSNode<T>,
-    mcs_lock: &'a MCSLock<T>,
+pub struct MCSLockGuard<'a, T, L: Layout = Padded> {
+    node: &'a mut MCSNode<T, L>,
+    mcs_lock: &'a MCSLock<T, L>,
 }
 
-unsafe impl<T> Sync for MCSLock<T> {}
-unsafe impl<T> Send for MCSLock<T> {}
+unsafe impl<T, L: Layout> Sync for MCSLock<T, L> {}
+unsafe impl<T, L: Layout> Send for MCSLock<T, L> {}
+unsafe impl<T, L: Layout> Send for MCSNode<T, L> {}
 
 impl<T> MCSNode<T> {
     pub fn new() -> Self {
+        Self::with_layout()
+    }
+}
+
+impl<T, L: Layout> MCSNode<T, L> {
+    pub fn with_layout() -> Self {
         Self {
-            next: AtomicPtr::new(null_mut()),
-            locked: AtomicBool::new(false),
+            qnode: QNode::alloc(),
+            _marker: PhantomData,
         }
     }
+
+    fn qnode(&self) -> &QNode<L> {
+        unsafe { &*self.qnode }
+    }
 }
 
 impl<T> Default for MCSNode<T> {
@@ -38,7 +73,13 @@ impl<T> Default for MCSNode<T> {
     }
 }
 
-impl<'a, T> Deref for MCSLockGuard<'a, T> {
+impl<T, L: Layout> Drop for MCSNode<T, L> {
+    fn drop(&mut self) {
+        unsafe { drop(Box::from_raw(self.qnode)) };
+    }
+}
+
+impl<'a, T, L: Layout> Deref for MCSLockGuard<'a, T, L> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
@@ -46,7 +87,7 @@ impl<'a, T> Deref for MCSLockGuard<'a, T> {
     }
 }
 
-impl<'a, T> DerefMut for MCSLockGuard<'a, T> {
+impl<'a, T, L: Layout> DerefMut for MCSLockGuard<'a, T, L> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         unsafe { &mut *self.mcs_lock.data.get() }
     }
@@ -54,55 +95,129 @@ impl<'a, T> DerefMut for MCSLockGuard<'a, T> {
 
 impl<T> MCSLock<T> {
     pub fn new(v: T) -> Self {
+        Self::with_layout(v)
+    }
+}
+
+impl<T, L: Layout> MCSLock<T, L> {
+    pub fn with_layout(v: T) -> Self {
         Self {
-            last: AtomicPtr::new(null_mut()),
+            last: L::slot(AtomicPtr::new(null_mut())),
             data: UnsafeCell::new(v),
         }
     }
 
-    pub fn lock<'a>(&'a self, node: &'a mut MCSNode<T>) -> MCSLockGuard<T> {
-        node.next = AtomicPtr::new(null_mut());
-        node.locked = AtomicBool::new(false);
+    fn enqueue(&self, node: &MCSNode<T, L>) -> bool {
+        let qnode = node.qnode();
+        qnode.next.store(null_mut(), Ordering::Relaxed);
+        qnode.state.store(WAITING, Ordering::Relaxed);
 
-        let guard = MCSLockGuard {
-            node,
-            mcs_lock: self,
-        };
+        let prev = self.last.swap(node.qnode, Ordering::AcqRel);
+        if prev.is_null() {
+            return false;
+        }
 
-        let ptr = guard.node as *mut MCSNode<T>;
-        let prev = self.last.swap(ptr, Ordering::Relaxed);
+        let prev = unsafe { &*prev };
+        prev.next.store(node.qnode, Ordering::Release);
+        true
+    }
 
-        if !prev.is_null() {
-            guard.node.locked.store(true, Ordering::Relaxed);
+    pub fn lock<'a>(&'a self, node: &'a mut MCSNode<T, L>) -> MCSLockGuard<'a, T, L> {
+        if self.enqueue(node) {
+            while node.qnode().state.load(Ordering::Relaxed) != GRANTED {
+                std::hint::spin_loop();
+            }
+        }
 
-            let prev = unsafe { &*prev };
-            prev.next.store(ptr, Ordering::Relaxed);
+        fence(Ordering::Acquire);
+        MCSLockGuard {
+            node,
+            mcs_lock: self,
+        }
+    }
 
-            while guard.node.locked.load(Ordering::Relaxed) {}
+    pub fn lock_timeout<'a>(
+        &'a self,
+        node: &'a mut MCSNode<T, L>,
+        timeout: Duration,
+    ) -> Option<MCSLockGuard<'a, T, L>> {
+        let deadline = Instant::now() + timeout;
+
+        if self.enqueue(node) {
+            while node.qnode().state.load(Ordering::Relaxed) != GRANTED {
+                if Instant::now() < deadline {
+                    std::hint::spin_loop();
+                    continue;
+                }
+
+                if node
+                    .qnode()
+                    .state
+                    .compare_exchange(WAITING, ABANDONED, Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    // the abandoned qnode stays in the queue and is freed by the lock holder
+                    node.qnode = QNode::alloc();
+                    return None;
+                }
+            }
         }
 
         fence(Ordering::Acquire);
-        guard
+        Some(MCSLockGuard {
+            node,
+            mcs_lock: self,
+        })
     }
 }
 
-impl<'a, T> Drop for MCSLockGuard<'a, T> {
+impl<'a, T, L: Layout> MCSLockGuard<'a, T, L> {
+    pub fn has_successor(&self) -> bool {
+        !self.node.qnode().next.load(Ordering::Acquire).is_null()
+            || self.mcs_lock.last.load(Ordering::Relaxed) != self.node.qnode
+    }
+}
+
+impl<'a, T, L: Layout> Drop for MCSLockGuard<'a, T, L> {
     fn drop(&mut self) {
-        if self.node.next.load(Ordering::Relaxed).is_null() {
-            let ptr = self.node as *mut MCSNode<T>;
-            if self
-                .mcs_lock
-                .last
-                .compare_exchange(ptr, null_mut(), Ordering::Release, Ordering::Relaxed)
+        let own = self.node.qnode;
+        let mut ptr = own;
+
+        loop {
+            let qnode = unsafe { &*ptr };
+
+            if qnode.next.load(Ordering::Acquire).is_null() {
+                if self
+                    .mcs_lock
+                    .last
+                    .compare_exchange(ptr, null_mut(), Ordering::Release, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    if ptr != own {
+                        unsafe { drop(Box::from_raw(ptr)) };
+                    }
+                    return;
+                }
+
+                while qnode.next.load(Ordering::Acquire).is_null() {
+                    std::hint::spin_loop();
+                }
+            }
+
+            let next = qnode.next.load(Ordering::Acquire);
+            if ptr != own {
+                unsafe { drop(Box::from_raw(ptr)) };
+            }
+
+            if unsafe { &*next }
+                .state
+                .compare_exchange(WAITING, GRANTED, Ordering::Release, Ordering::Acquire)
                 .is_ok()
             {
                 return;
             }
-        }
 
-        while self.node.next.load(Ordering::Relaxed).is_null() {}
-
-        let next = unsafe { &mut *self.node.next.load(Ordering::Relaxed) };
-        next.locked.store(false, Ordering::Release);
+            ptr = next;
+        }
     }
 }
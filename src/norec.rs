@@ -0,0 +1,345 @@
+use std::{
+    collections::HashMap,
+    mem::size_of,
+    sync::{
+        atomic::{fence, AtomicU64, AtomicU8, AtomicUsize, Ordering},
+        Condvar, Mutex, OnceLock,
+    },
+};
+
+pub use crate::stm::{NoUninit, STMError, STMResult, TVar, Transaction, WriteTransaction};
+use crate::{
+    cache_padded::CachePadded,
+    stm::{as_bytes, decode, next_stm_id, Stm},
+};
+
+const SEGMENT_SIZE: usize = 4096;
+const NUM_SEGMENTS: usize = 48;
+const ALIGN: usize = 8;
+
+struct Memory {
+    segments: Vec<OnceLock<Box<[AtomicU8]>>>,
+}
+
+impl Memory {
+    fn new() -> Self {
+        Self {
+            segments: (0..NUM_SEGMENTS).map(|_| OnceLock::new()).collect(),
+        }
+    }
+
+    fn byte(&self, addr: usize) -> &AtomicU8 {
+        let n = addr / SEGMENT_SIZE + 1;
+        let seg = (usize::BITS - 1 - n.leading_zeros()) as usize;
+        let offset = addr - SEGMENT_SIZE * ((1 << seg) - 1);
+
+        let segment = self.segments[seg]
+            .get_or_init(|| (0..SEGMENT_SIZE << seg).map(|_| AtomicU8::new(0)).collect());
+        &segment[offset]
+    }
+
+    fn read(&self, addr: usize, len: usize) -> Vec<u8> {
+        (addr..addr + len)
+            .map(|a| self.byte(a).load(Ordering::Relaxed))
+            .collect()
+    }
+
+    fn write(&self, addr: usize, val: &[u8]) {
+        for (i, b) in val.iter().enumerate() {
+            self.byte(addr + i).store(*b, Ordering::Relaxed);
+        }
+    }
+}
+
+pub struct ReadTrans<'a> {
+    snapshot: u64,
+    read_log: Vec<(usize, Vec<u8>)>,
+    is_abort: bool,
+    stm: &'a STM,
+}
+
+impl<'a> ReadTrans<'a> {
+    fn new(stm: &'a STM) -> Self {
+        Self {
+            snapshot: stm.begin(),
+            read_log: Vec::new(),
+            is_abort: false,
+            stm,
+        }
+    }
+
+    fn validate(&mut self) -> Option<u64> {
+        loop {
+            let time = self.stm.seq.load(Ordering::Acquire);
+            if time & 1 != 0 {
+                std::hint::spin_loop();
+                continue;
+            }
+
+            let is_valid = self
+                .read_log
+                .iter()
+                .all(|(addr, val)| self.stm.mem.read(*addr, val.len()) == *val);
+
+            fence(Ordering::Acquire);
+
+            if self.stm.seq.load(Ordering::Relaxed) == time {
+                if is_valid {
+                    return Some(time);
+                }
+                self.is_abort = true;
+                return None;
+            }
+        }
+    }
+
+    fn load(&mut self, addr: usize, len: usize) -> Option<Vec<u8>> {
+        if self.is_abort {
+            return None;
+        }
+
+        let mut val = self.stm.mem.read(addr, len);
+        fence(Ordering::Acquire);
+        while self.stm.seq.load(Ordering::Relaxed) != self.snapshot {
+            self.snapshot = self.validate()?;
+            val = self.stm.mem.read(addr, len);
+            fence(Ordering::Acquire);
+        }
+
+        self.read_log.push((addr, val.clone()));
+        Some(val)
+    }
+
+    pub fn read<T: NoUninit>(&mut self, tvar: &TVar<T>) -> Option<T> {
+        let bytes = self.load(tvar.addr_in(self.stm.id), size_of::<T>())?;
+        Some(decode(&bytes))
+    }
+}
+
+pub struct WriteTrans<'a> {
+    reads: ReadTrans<'a>,
+    write_set: HashMap<usize, Vec<u8>>,
+}
+
+impl<'a> WriteTrans<'a> {
+    fn new(stm: &'a STM) -> Self {
+        Self {
+            reads: ReadTrans::new(stm),
+            write_set: HashMap::new(),
+        }
+    }
+
+    pub fn read<T: NoUninit>(&mut self, tvar: &TVar<T>) -> Option<T> {
+        if self.reads.is_abort {
+            return None;
+        }
+
+        if let Some(val) = self.write_set.get(&tvar.addr_in(self.reads.stm.id)) {
+            return Some(decode(val));
+        }
+
+        self.reads.read(tvar)
+    }
+
+    pub fn write<T: NoUninit>(&mut self, tvar: &TVar<T>, val: T) {
+        self.write_set
+            .insert(tvar.addr_in(self.reads.stm.id), as_bytes(&val).to_vec());
+    }
+
+    fn commit(&mut self) -> bool {
+        if self.write_set.is_empty() {
+            return true;
+        }
+
+        let seq = &self.reads.stm.seq;
+        while seq
+            .compare_exchange(
+                self.reads.snapshot,
+                self.reads.snapshot + 1,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            )
+            .is_err()
+        {
+            match self.reads.validate() {
+                Some(time) => self.reads.snapshot = time,
+                None => return false,
+            }
+        }
+
+        fence(Ordering::Release);
+
+        for (&addr, val) in &self.write_set {
+            self.reads.stm.mem.write(addr, val);
+        }
+
+        seq.store(self.reads.snapshot + 2, Ordering::Release);
+        true
+    }
+}
+
+impl<'a> Transaction for ReadTrans<'a> {
+    fn read<T: NoUninit>(&mut self, tvar: &TVar<T>) -> Option<T> {
+        ReadTrans::read(self, tvar)
+    }
+}
+
+impl<'a> Transaction for WriteTrans<'a> {
+    fn read<T: NoUninit>(&mut self, tvar: &TVar<T>) -> Option<T> {
+        WriteTrans::read(self, tvar)
+    }
+}
+
+impl<'a> WriteTransaction for WriteTrans<'a> {
+    fn write<T: NoUninit>(&mut self, tvar: &TVar<T>, val: T) {
+        WriteTrans::write(self, tvar, val)
+    }
+}
+
+pub struct STM {
+    id: usize,
+    seq: CachePadded<AtomicU64>,
+    mem: Memory,
+    brk: AtomicUsize,
+    waiters: AtomicUsize,
+    retry_lock: Mutex<()>,
+    retry_cond: Condvar,
+}
+
+impl STM {
+    pub fn new() -> Self {
+        Self {
+            id: next_stm_id(),
+            seq: CachePadded::new(AtomicU64::new(0)),
+            mem: Memory::new(),
+            brk: AtomicUsize::new(0),
+            waiters: AtomicUsize::new(0),
+            retry_lock: Mutex::new(()),
+            retry_cond: Condvar::new(),
+        }
+    }
+
+    pub fn alloc<T: NoUninit>(&self, init: T) -> TVar<T> {
+        let size = size_of::<T>().max(1).div_ceil(ALIGN) * ALIGN;
+        let tvar = TVar::new(self.id, self.brk.fetch_add(size, Ordering::Relaxed));
+
+        self.write_transaction(|tr| {
+            tr.write(&tvar, init);
+            STMResult::Ok(())
+        });
+
+        tvar
+    }
+
+    fn begin(&self) -> u64 {
+        loop {
+            let time = self.seq.load(Ordering::Acquire);
+            if time & 1 == 0 {
+                return time;
+            }
+            std::hint::spin_loop();
+        }
+    }
+
+    fn wait_for_commit(&self, tr: &ReadTrans) -> bool {
+        if tr.read_log.is_empty() {
+            return false;
+        }
+
+        let mut guard = self.retry_lock.lock().unwrap();
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        fence(Ordering::SeqCst);
+        while self.seq.load(Ordering::Relaxed) == tr.snapshot {
+            guard = self.retry_cond.wait(guard).unwrap();
+        }
+        self.waiters.fetch_sub(1, Ordering::Relaxed);
+
+        true
+    }
+
+    fn notify_commit(&self) {
+        fence(Ordering::SeqCst);
+        if self.waiters.load(Ordering::SeqCst) > 0 {
+            let _guard = self.retry_lock.lock().unwrap();
+            self.retry_cond.notify_all();
+        }
+    }
+
+    pub fn read_transaction<R>(&self, f: impl Fn(&mut ReadTrans) -> STMResult<R>) -> Option<R> {
+        self.try_read_transaction(f).ok()
+    }
+
+    pub fn try_read_transaction<R>(
+        &self,
+        f: impl Fn(&mut ReadTrans) -> STMResult<R>,
+    ) -> Result<R, STMError> {
+        loop {
+            let mut tr = ReadTrans::new(self);
+
+            match f(&mut tr) {
+                STMResult::Abort => return Err(STMError::Abort),
+                _ if tr.is_abort => (),
+                STMResult::Retry => {
+                    if !self.wait_for_commit(&tr) {
+                        return Err(STMError::Retry);
+                    }
+                }
+                STMResult::Ok(val) => return Ok(val),
+            }
+        }
+    }
+
+    pub fn write_transaction<R>(&self, f: impl Fn(&mut WriteTrans) -> STMResult<R>) -> Option<R> {
+        self.try_write_transaction(f).ok()
+    }
+
+    pub fn try_write_transaction<R>(
+        &self,
+        f: impl Fn(&mut WriteTrans) -> STMResult<R>,
+    ) -> Result<R, STMError> {
+        loop {
+            let mut tr = WriteTrans::new(self);
+
+            match f(&mut tr) {
+                STMResult::Abort => return Err(STMError::Abort),
+                _ if tr.reads.is_abort => (),
+                STMResult::Retry => {
+                    if !self.wait_for_commit(&tr.reads) {
+                        return Err(STMError::Retry);
+                    }
+                }
+                STMResult::Ok(val) => {
+                    if tr.commit() {
+                        if !tr.write_set.is_empty() {
+                            self.notify_commit();
+                        }
+                        return Ok(val);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Default for STM {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stm for STM {
+    type ReadTrans<'a> = ReadTrans<'a>;
+    type WriteTrans<'a> = WriteTrans<'a>;
+
+    fn alloc<T: NoUninit>(&self, init: T) -> TVar<T> {
+        STM::alloc(self, init)
+    }
+
+    fn read_transaction<R>(&self, f: impl Fn(&mut ReadTrans) -> STMResult<R>) -> Option<R> {
+        STM::read_transaction(self, f)
+    }
+
+    fn write_transaction<R>(&self, f: impl Fn(&mut WriteTrans) -> STMResult<R>) -> Option<R> {
+        STM::write_transaction(self, f)
+    }
+}
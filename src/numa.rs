@@ -0,0 +1,100 @@
+use std::fs;
+
+const NODE_DIR: &str = "/sys/devices/system/node";
+
+#[derive(Debug, Clone)]
+pub struct Topology {
+    cpu_to_node: Vec<usize>,
+    num_nodes: usize,
+}
+
+impl Topology {
+    pub fn detect() -> Self {
+        let mut nodes = Vec::new();
+        if let Ok(entries) = fs::read_dir(NODE_DIR) {
+            for entry in entries.flatten() {
+                let name = entry.file_name();
+                let id = match name
+                    .to_str()
+                    .and_then(|s| s.strip_prefix("node"))
+                    .and_then(|s| s.parse::<usize>().ok())
+                {
+                    Some(id) => id,
+                    None => continue,
+                };
+
+                if let Ok(list) = fs::read_to_string(entry.path().join("cpulist")) {
+                    nodes.push((id, parse_cpulist(&list)));
+                }
+            }
+        }
+
+        if nodes.is_empty() {
+            return Self::single();
+        }
+
+        nodes.sort_unstable_by_key(|(id, _)| *id);
+
+        let num_cpus = nodes
+            .iter()
+            .flat_map(|(_, cpus)| cpus.iter())
+            .max()
+            .map_or(0, |&cpu| cpu + 1);
+        let mut cpu_to_node = vec![0; num_cpus];
+        for (idx, (_, cpus)) in nodes.iter().enumerate() {
+            for &cpu in cpus {
+                cpu_to_node[cpu] = idx;
+            }
+        }
+
+        Self {
+            cpu_to_node,
+            num_nodes: nodes.len(),
+        }
+    }
+
+    pub fn new(cpu_to_node: Vec<usize>) -> Self {
+        let num_nodes = cpu_to_node.iter().max().map_or(1, |&n| n + 1);
+        Self {
+            cpu_to_node,
+            num_nodes,
+        }
+    }
+
+    pub fn single() -> Self {
+        Self {
+            cpu_to_node: Vec::new(),
+            num_nodes: 1,
+        }
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.num_nodes
+    }
+
+    pub fn node_of_cpu(&self, cpu: usize) -> usize {
+        self.cpu_to_node.get(cpu).copied().unwrap_or(0)
+    }
+
+    pub fn current_node(&self) -> usize {
+        let cpu = unsafe { nix::libc::sched_getcpu() };
+        if cpu < 0 {
+            0
+        } else {
+            self.node_of_cpu(cpu as usize)
+        }
+    }
+}
+
+fn parse_cpulist(list: &str) -> Vec<usize> {
+    let mut cpus = Vec::new();
+    for range in list.trim().split(',').filter(|s| !s.is_empty()) {
+        let mut bounds = range.splitn(2, '-').map(|s| s.parse::<usize>());
+        match (bounds.next(), bounds.next()) {
+            (Some(Ok(lo)), Some(Ok(hi))) => cpus.extend(lo..=hi),
+            (Some(Ok(cpu)), None) => cpus.push(cpu),
+            _ => (),
+        }
+    }
+    cpus
+}
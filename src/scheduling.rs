@@ -54,6 +54,12 @@ impl Executor {
     }
 }
 
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct Spawner {
     sender: SyncSender<Arc<Task>>,
 }
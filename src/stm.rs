@@ -0,0 +1,134 @@
+use std::{
+    marker::PhantomData,
+    mem::{size_of, MaybeUninit},
+    ptr, slice,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static NEXT_STM_ID: AtomicUsize = AtomicUsize::new(0);
+
+// every STM instance gets its own id, so a TVar can't be used with another one
+pub(crate) fn next_stm_id() -> usize {
+    NEXT_STM_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+pub struct TVar<T> {
+    stm: usize,
+    addr: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Clone for TVar<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for TVar<T> {}
+
+impl<T> TVar<T> {
+    pub(crate) fn new(stm: usize, addr: usize) -> Self {
+        Self {
+            stm,
+            addr,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn addr(&self) -> usize {
+        self.addr
+    }
+
+    // the address in the STM `stm`; panics if the TVar was allocated by another
+    pub(crate) fn addr_in(&self, stm: usize) -> usize {
+        assert_eq!(
+            self.stm, stm,
+            "TVar used with an STM that did not allocate it"
+        );
+        self.addr
+    }
+}
+
+/// Types that can be kept in a [`TVar`]. The STMs store values as plain
+/// bytes, so a value is copied into memory byte by byte and read back with an
+/// unaligned load.
+///
+/// # Safety
+///
+/// The type must not contain padding or any other uninitialized bytes, and it
+/// must be safe to send to another thread. Bytes are only ever decoded as the
+/// type that wrote them: a [`TVar`] is tied to the STM that allocated it, and
+/// using it with another STM panics. So not every bit pattern has to be valid.
+pub unsafe trait NoUninit: Copy + Send + 'static {}
+
+macro_rules! no_uninit {
+    ($($t:ty),*) => {
+        $(unsafe impl NoUninit for $t {})*
+    };
+}
+
+no_uninit!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, bool, char);
+
+unsafe impl<T: NoUninit, const N: usize> NoUninit for [T; N] {}
+
+pub(crate) fn as_bytes<T: NoUninit>(v: &T) -> &[u8] {
+    unsafe { slice::from_raw_parts(v as *const T as *const u8, size_of::<T>()) }
+}
+
+// only called on bytes written by as_bytes for the same TVar, hence the same T
+pub(crate) fn decode<T: NoUninit>(bytes: &[u8]) -> T {
+    assert!(bytes.len() >= size_of::<T>());
+    unsafe { ptr::read_unaligned(bytes.as_ptr() as *const T) }
+}
+
+// decodes a T in place from the bytes `fill` copies in, without a buffer; the
+// bytes must again come from a T
+pub(crate) fn decode_with<T: NoUninit>(fill: impl FnOnce(&mut [u8]) -> Option<()>) -> Option<T> {
+    // zeroed, so the slice below never covers uninitialized bytes
+    let mut val = MaybeUninit::<T>::zeroed();
+    let bytes = unsafe { slice::from_raw_parts_mut(val.as_mut_ptr() as *mut u8, size_of::<T>()) };
+    fill(bytes)?;
+    Some(unsafe { val.assume_init() })
+}
+
+pub enum STMResult<T> {
+    Ok(T),
+    Retry,
+    Abort,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum STMError {
+    Abort,
+    Retry,
+    RetryLimit { aborts: usize },
+}
+
+pub trait Transaction {
+    fn read<T: NoUninit>(&mut self, tvar: &TVar<T>) -> Option<T>;
+}
+
+pub trait WriteTransaction: Transaction {
+    fn write<T: NoUninit>(&mut self, tvar: &TVar<T>, val: T);
+}
+
+pub trait Stm: Send + Sync {
+    type ReadTrans<'a>: Transaction
+    where
+        Self: 'a;
+    type WriteTrans<'a>: WriteTransaction
+    where
+        Self: 'a;
+
+    fn alloc<T: NoUninit>(&self, init: T) -> TVar<T>;
+
+    fn read_transaction<R>(
+        &self,
+        f: impl Fn(&mut Self::ReadTrans<'_>) -> STMResult<R>,
+    ) -> Option<R>;
+
+    fn write_transaction<R>(
+        &self,
+        f: impl Fn(&mut Self::WriteTrans<'_>) -> STMResult<R>,
+    ) -> Option<R>;
+}
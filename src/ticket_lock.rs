@@ -0,0 +1,74 @@
+use std::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::cache_padded::CachePadded;
+
+pub struct TicketLock<T> {
+    next: CachePadded<AtomicUsize>,
+    serving: CachePadded<AtomicUsize>,
+    data: UnsafeCell<T>,
+}
+
+pub struct TicketLockGuard<'a, T> {
+    ticket_lock: &'a TicketLock<T>,
+}
+
+unsafe impl<T> Sync for TicketLock<T> {}
+unsafe impl<T> Send for TicketLock<T> {}
+
+impl<T> TicketLock<T> {
+    pub fn new(v: T) -> Self {
+        Self {
+            next: CachePadded::new(AtomicUsize::new(0)),
+            serving: CachePadded::new(AtomicUsize::new(0)),
+            data: UnsafeCell::new(v),
+        }
+    }
+
+    pub fn lock(&self) -> TicketLockGuard<'_, T> {
+        let ticket = self.next.fetch_add(1, Ordering::Relaxed);
+        while self.serving.load(Ordering::Acquire) != ticket {
+            std::hint::spin_loop();
+        }
+
+        TicketLockGuard { ticket_lock: self }
+    }
+
+    pub fn try_lock(&self) -> Option<TicketLockGuard<'_, T>> {
+        let serving = self.serving.load(Ordering::Acquire);
+        self.next
+            .compare_exchange(
+                serving,
+                serving.wrapping_add(1),
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .ok()
+            .map(|_| TicketLockGuard { ticket_lock: self })
+    }
+}
+
+impl<'a, T> Drop for TicketLockGuard<'a, T> {
+    fn drop(&mut self) {
+        let serving = &self.ticket_lock.serving;
+        let next = serving.load(Ordering::Relaxed).wrapping_add(1);
+        serving.store(next, Ordering::Release);
+    }
+}
+
+impl<'a, T> Deref for TicketLockGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.ticket_lock.data.get() }
+    }
+}
+
+impl<'a, T> DerefMut for TicketLockGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.ticket_lock.data.get() }
+    }
+}
@@ -1,57 +1,101 @@
 use std::{
-    cell::UnsafeCell,
-    collections::{HashMap, HashSet},
-    convert::TryInto,
-    sync::atomic::{fence, AtomicU64, Ordering},
+    cell::Cell,
+    mem::{self, size_of},
+    sync::{
+        atomic::{fence, AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering},
+        Condvar, Mutex, MutexGuard, PoisonError,
+    },
+    task::Waker,
 };
 
-const STRIPE_SIZE: usize = 8;
-const MEM_SIZE: usize = 512;
-const STRIPE_NUM: usize = MEM_SIZE / STRIPE_SIZE;
+pub mod atomically;
+pub mod collections;
+pub mod contention;
+mod sets;
+pub mod stats;
+
+use atomically::Wakers;
+use contention::{Aggressive, ContentionManager, Resolution, TransStat};
+use sets::{ReadSet, WriteSet};
+use stats::{AbortCause, Counters, Stats};
+
+use crate::stm::{as_bytes, decode_with, next_stm_id, Stm};
+pub use crate::stm::{NoUninit, STMError, STMResult, TVar, Transaction, WriteTransaction};
+
+pub const DEFAULT_STRIPE_SIZE: usize = 8;
+pub const DEFAULT_MEM_SIZE: usize = 512;
 
 pub struct Memory {
-    mem: [u8; MEM_SIZE],
-    lock_ver: [AtomicU64; STRIPE_NUM],
+    // id of the STM owning this memory, checked against every TVar
+    id: usize,
+    mem: Vec<AtomicU8>,
+    lock_ver: Vec<AtomicU64>,
+    // karma of the transaction that last locked each stripe, so that the
+    // contention manager can weigh a conflict against its owner
+    owner_karma: Vec<AtomicUsize>,
     global_clock: AtomicU64,
+    stripe_size: usize,
+    shift_size: u32,
 }
 
 impl Memory {
-    const SHIFT_SIZE: u32 = STRIPE_SIZE.trailing_zeros();
-
     pub fn new() -> Self {
-        let mem = [0; MEM_SIZE];
+        Self::with_size(DEFAULT_MEM_SIZE, DEFAULT_STRIPE_SIZE)
+    }
+
+    pub fn with_size(mem_size: usize, stripe_size: usize) -> Self {
+        assert!(stripe_size.is_power_of_two());
+        assert!(mem_size > 0 && mem_size & (stripe_size - 1) == 0);
+
+        let shift_size = stripe_size.trailing_zeros();
 
-        let lock_ver = (0..MEM_SIZE >> Self::SHIFT_SIZE)
+        let mem = (0..mem_size).map(|_| AtomicU8::new(0)).collect();
+
+        let lock_ver = (0..mem_size >> shift_size)
             .map(|_| AtomicU64::new(0))
-            .collect::<Vec<_>>()
-            .try_into()
-            .unwrap();
+            .collect();
+
+        let owner_karma = (0..mem_size >> shift_size)
+            .map(|_| AtomicUsize::new(0))
+            .collect();
 
         Self {
+            id: next_stm_id(),
             mem,
             lock_ver,
+            owner_karma,
             global_clock: AtomicU64::new(0),
+            stripe_size,
+            shift_size,
         }
     }
 
-    fn inc_global_clock(&mut self) -> u64 {
+    pub fn mem_size(&self) -> usize {
+        self.mem.len()
+    }
+
+    pub fn stripe_size(&self) -> usize {
+        self.stripe_size
+    }
+
+    fn inc_global_clock(&self) -> u64 {
         self.global_clock.fetch_add(1, Ordering::AcqRel)
     }
 
     fn get_addr_ver(&self, addr: usize) -> u64 {
-        let idx = addr >> Self::SHIFT_SIZE;
+        let idx = addr >> self.shift_size;
         let n = self.lock_ver[idx].load(Ordering::Relaxed);
         n & !(1 << 63)
     }
 
     fn test_not_modify(&self, addr: usize, rv: u64) -> bool {
-        let idx = addr >> Self::SHIFT_SIZE;
+        let idx = addr >> self.shift_size;
         let n = self.lock_ver[idx].load(Ordering::Relaxed);
         n <= rv
     }
 
-    fn lock_addr(&mut self, addr: usize) -> bool {
-        let idx = addr >> Self::SHIFT_SIZE;
+    fn lock_addr(&self, addr: usize) -> bool {
+        let idx = addr >> self.shift_size;
         self.lock_ver[idx]
             .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |val| {
                 let n = val & (1 << 63);
@@ -64,10 +108,66 @@ impl Memory {
             .is_ok()
     }
 
-    fn unlock_addr(&mut self, addr: usize) {
-        let idx = addr >> Self::SHIFT_SIZE;
+    fn unlock_addr(&self, addr: usize) {
+        let idx = addr >> self.shift_size;
         self.lock_ver[idx].fetch_and(!(1 << 63), Ordering::Relaxed);
     }
+
+    fn set_owner_karma(&self, addr: usize, karma: usize) {
+        let idx = addr >> self.shift_size;
+        self.owner_karma[idx].store(karma, Ordering::Relaxed);
+    }
+
+    // a conflict on a stripe locked or last written by another transaction
+    fn conflict(&self, cause: AbortCause, addr: usize) -> Conflict {
+        let idx = addr >> self.shift_size;
+        Conflict {
+            cause,
+            addr,
+            enemy: Some(self.owner_karma[idx].load(Ordering::Relaxed)),
+        }
+    }
+
+    fn is_locked(&self, addr: usize) -> bool {
+        let idx = addr >> self.shift_size;
+        self.lock_ver[idx].load(Ordering::Relaxed) & (1 << 63) != 0
+    }
+
+    // waits until the stripe at addr is unlocked
+    fn wait_for_stripe(&self, addr: usize) {
+        while self.is_locked(addr) {
+            std::hint::spin_loop();
+        }
+    }
+
+    // waits until every stripe lock held at the time of the call is released
+    fn wait_for_unlock(&self) {
+        for lock_ver in &self.lock_ver {
+            while lock_ver.load(Ordering::Relaxed) & (1 << 63) != 0 {
+                std::hint::spin_loop();
+            }
+        }
+        fence(Ordering::Acquire);
+    }
+
+    fn is_modified(&self, read_set: &ReadSet, read_ver: u64) -> bool {
+        read_set
+            .iter()
+            .any(|addr| self.get_addr_ver(addr) > read_ver)
+    }
+
+    // copies the first buf.len() bytes of the stripe
+    fn read_stripe(&self, addr: usize, buf: &mut [u8]) {
+        for (dst, src) in buf.iter_mut().zip(&self.mem[addr..addr + self.stripe_size]) {
+            *dst = src.load(Ordering::Relaxed);
+        }
+    }
+
+    fn write_stripe(&self, addr: usize, val: &[u8]) {
+        for (dst, src) in self.mem[addr..addr + self.stripe_size].iter().zip(val) {
+            dst.store(*src, Ordering::Relaxed);
+        }
+    }
 }
 
 impl Default for Memory {
@@ -76,144 +176,320 @@ impl Default for Memory {
     }
 }
 
+fn num_stripes<T>(stripe_size: usize) -> usize {
+    size_of::<T>().div_ceil(stripe_size)
+}
+
+// why an attempt failed; enemy is the karma of the transaction owning the
+// stripe, or None if that is not known
+#[derive(Clone, Copy)]
+struct Conflict {
+    cause: AbortCause,
+    addr: usize,
+    enemy: Option<usize>,
+}
+
+type Hook = Box<dyn FnOnce()>;
+
+fn run_hooks(hooks: Vec<Hook>) {
+    for hook in hooks {
+        hook();
+    }
+}
+
 pub struct ReadTrans<'a> {
     read_ver: u64,
+    read_set: ReadSet,
     is_abort: bool,
+    conflict: Option<Conflict>,
+    aborts: usize,
     mem: &'a Memory,
 }
 
 impl<'a> ReadTrans<'a> {
-    fn new(mem: &'a Memory) -> Self {
+    fn new(mem: &'a Memory, aborts: usize) -> Self {
         Self {
             read_ver: mem.global_clock.load(Ordering::Acquire),
+            read_set: ReadSet::default(),
             is_abort: false,
+            conflict: None,
+            aborts,
             mem,
         }
     }
 
-    pub fn load(&mut self, addr: usize) -> Option<[u8; STRIPE_SIZE]> {
+    pub fn aborts(&self) -> usize {
+        self.aborts
+    }
+
+    /// Copies the first `buf.len()` bytes of the stripe at `addr` into `buf`.
+    pub fn load(&mut self, addr: usize, buf: &mut [u8]) -> Option<()> {
         if self.is_abort {
             return None;
         }
-        assert_eq!(addr & (STRIPE_SIZE - 1), 0);
+        let stripe_size = self.mem.stripe_size;
+        assert_eq!(addr & (stripe_size - 1), 0);
+        assert!(buf.len() <= stripe_size);
+
+        self.read_set.insert(addr);
 
         if !self.mem.test_not_modify(addr, self.read_ver) {
             self.is_abort = true;
+            self.conflict = Some(self.mem.conflict(AbortCause::Load, addr));
             return None;
         }
 
         fence(Ordering::Acquire);
 
-        let mut mem = [0; STRIPE_SIZE];
-        for (dst, src) in mem
-            .iter_mut()
-            .zip(self.mem.mem[addr..addr + STRIPE_SIZE].iter())
-        {
-            *dst = *src;
-        }
+        self.mem.read_stripe(addr, buf);
 
         fence(Ordering::SeqCst);
 
         if !self.mem.test_not_modify(addr, self.read_ver) {
             self.is_abort = true;
+            self.conflict = Some(self.mem.conflict(AbortCause::Load, addr));
             return None;
         }
 
-        Some(mem)
+        Some(())
+    }
+
+    pub fn read<T: NoUninit>(&mut self, tvar: &TVar<T>) -> Option<T> {
+        let stripe_size = self.mem.stripe_size;
+        let addr = tvar.addr_in(self.mem.id);
+        decode_with(|bytes| {
+            for (i, chunk) in bytes.chunks_mut(stripe_size).enumerate() {
+                self.load(addr + i * stripe_size, chunk)?;
+            }
+            Some(())
+        })
+    }
+}
+
+impl<'a> Transaction for ReadTrans<'a> {
+    fn read<T: NoUninit>(&mut self, tvar: &TVar<T>) -> Option<T> {
+        ReadTrans::read(self, tvar)
+    }
+}
+
+impl<'a> Transaction for WriteTrans<'a> {
+    fn read<T: NoUninit>(&mut self, tvar: &TVar<T>) -> Option<T> {
+        WriteTrans::read(self, tvar)
+    }
+}
+
+impl<'a> WriteTransaction for WriteTrans<'a> {
+    fn write<T: NoUninit>(&mut self, tvar: &TVar<T>, val: T) {
+        WriteTrans::write(self, tvar, val)
     }
 }
 
 pub struct WriteTrans<'a> {
     read_ver: u64,
-    read_set: HashSet<usize>,
-    write_set: HashMap<usize, [u8; STRIPE_SIZE]>,
+    read_set: ReadSet,
+    write_set: WriteSet,
     locked: Vec<usize>,
+    commit_hooks: Vec<Hook>,
+    abort_hooks: Vec<Hook>,
     is_abort: bool,
-    mem: &'a mut Memory,
+    is_irrevocable: bool,
+    conflict: Option<Conflict>,
+    aborts: usize,
+    karma: usize,
+    mem: &'a Memory,
+}
+
+struct Savepoint {
+    write_set: WriteSet,
+    commit_hooks: usize,
+    abort_hooks: usize,
 }
 
 impl<'a> Drop for WriteTrans<'a> {
     fn drop(&mut self) {
-        for &addr in &self.locked {
-            self.mem.unlock_addr(addr);
-        }
+        self.unlock();
     }
 }
 
 impl<'a> WriteTrans<'a> {
-    fn new(mem: &'a mut Memory) -> Self {
+    fn new(mem: &'a Memory, aborts: usize) -> Self {
         Self {
-            read_set: HashSet::new(),
-            write_set: HashMap::new(),
+            read_set: ReadSet::default(),
+            write_set: WriteSet::new(mem.stripe_size),
             locked: Vec::new(),
+            commit_hooks: Vec::new(),
+            abort_hooks: Vec::new(),
             is_abort: false,
+            is_irrevocable: false,
+            conflict: None,
+            aborts,
+            karma: 0,
             read_ver: mem.global_clock.load(Ordering::Acquire),
             mem,
         }
     }
 
-    pub fn store(&mut self, addr: usize, val: [u8; STRIPE_SIZE]) {
-        assert_eq!(addr & (STRIPE_SIZE - 1), 0);
-        self.write_set.insert(addr, val);
+    pub fn aborts(&self) -> usize {
+        self.aborts
     }
 
-    pub fn load(&mut self, addr: usize) -> Option<[u8; STRIPE_SIZE]> {
+    /// Copies the first `buf.len()` bytes of the stripe at `addr` into `buf`.
+    pub fn load(&mut self, addr: usize, buf: &mut [u8]) -> Option<()> {
         if self.is_abort {
             return None;
         }
 
-        assert_eq!(addr & (STRIPE_SIZE - 1), 0);
+        let stripe_size = self.mem.stripe_size;
+        assert_eq!(addr & (stripe_size - 1), 0);
+        assert!(buf.len() <= stripe_size);
 
         self.read_set.insert(addr);
 
-        if let Some(&m) = self.write_set.get(&addr) {
-            return Some(m);
+        if let Some(m) = self.write_set.get(addr) {
+            buf.copy_from_slice(&m[..buf.len()]);
+            return Some(());
+        }
+
+        // nobody else can commit, and a committer that briefly locks the
+        // stripe before backing off never writes it
+        if self.is_irrevocable {
+            self.mem.read_stripe(addr, buf);
+            return Some(());
         }
 
         if !self.mem.test_not_modify(addr, self.read_ver) {
             self.is_abort = true;
+            self.conflict = Some(self.mem.conflict(AbortCause::Load, addr));
             return None;
         }
 
         fence(Ordering::Acquire);
 
-        let mut mem = [0; STRIPE_SIZE];
-        for (dst, src) in mem
-            .iter_mut()
-            .zip(self.mem.mem[addr..addr + STRIPE_SIZE].iter())
-        {
-            *dst = *src;
-        }
+        self.mem.read_stripe(addr, buf);
 
         fence(Ordering::SeqCst);
 
         if !self.mem.test_not_modify(addr, self.read_ver) {
             self.is_abort = true;
+            self.conflict = Some(self.mem.conflict(AbortCause::Load, addr));
             return None;
         }
 
-        Some(mem)
+        Some(())
     }
 
-    fn lock_write_set(&mut self) -> bool {
-        for (&addr, _) in self.write_set.iter() {
-            if self.mem.lock_addr(addr) {
-                self.locked.push(addr);
-            } else {
-                return false;
+    pub fn read<T: NoUninit>(&mut self, tvar: &TVar<T>) -> Option<T> {
+        let stripe_size = self.mem.stripe_size;
+        let addr = tvar.addr_in(self.mem.id);
+        decode_with(|bytes| {
+            for (i, chunk) in bytes.chunks_mut(stripe_size).enumerate() {
+                self.load(addr + i * stripe_size, chunk)?;
             }
+            Some(())
+        })
+    }
+
+    pub fn write<T: NoUninit>(&mut self, tvar: &TVar<T>, val: T) {
+        let stripe_size = self.mem.stripe_size;
+        let addr = tvar.addr_in(self.mem.id);
+        for (i, chunk) in as_bytes(&val).chunks(stripe_size).enumerate() {
+            self.write_set.insert(addr + i * stripe_size, chunk);
+        }
+    }
+
+    /// Runs `f` once the transaction has committed. Hooks registered by an
+    /// attempt that is rolled back or re-executed are discarded.
+    pub fn on_commit(&mut self, f: impl FnOnce() + 'static) {
+        self.commit_hooks.push(Box::new(f));
+    }
+
+    /// Runs `f` if the transaction finally fails, i.e. it returns `Abort`,
+    /// gives up on `Retry` or is stopped by the contention manager.
+    pub fn on_abort(&mut self, f: impl FnOnce() + 'static) {
+        self.abort_hooks.push(Box::new(f));
+    }
+
+    fn savepoint(&self) -> Savepoint {
+        Savepoint {
+            write_set: self.write_set.clone(),
+            commit_hooks: self.commit_hooks.len(),
+            abort_hooks: self.abort_hooks.len(),
+        }
+    }
+
+    fn rollback(&mut self, sp: Savepoint) {
+        self.write_set = sp.write_set;
+        self.commit_hooks.truncate(sp.commit_hooks);
+        self.abort_hooks.truncate(sp.abort_hooks);
+    }
+
+    pub fn nested<R>(
+        &mut self,
+        f: impl FnOnce(&mut WriteTrans<'a>) -> STMResult<R>,
+    ) -> STMResult<R> {
+        let sp = self.savepoint();
+        let result = f(self);
+        if let STMResult::Abort = result {
+            self.rollback(sp);
+        }
+        result
+    }
+
+    pub fn or_else<R>(
+        &mut self,
+        first: impl FnOnce(&mut WriteTrans<'a>) -> STMResult<R>,
+        second: impl FnOnce(&mut WriteTrans<'a>) -> STMResult<R>,
+    ) -> STMResult<R> {
+        let sp = self.savepoint();
+        match first(self) {
+            STMResult::Retry if !self.is_abort => {
+                self.rollback(sp);
+                second(self)
+            }
+            result => result,
+        }
+    }
+
+    fn unlock(&mut self) {
+        for addr in self.locked.drain(..) {
+            self.mem.unlock_addr(addr);
         }
-        true
     }
 
-    fn validate_read_set(&self) -> bool {
-        for &addr in &self.read_set {
-            if self.write_set.contains_key(&addr) {
-                let ver = self.mem.get_addr_ver(addr);
-                if ver > self.read_ver {
+    fn lock_write_set(&mut self) -> bool {
+        for (addr, _) in self.write_set.iter() {
+            // an irrevocable transaction only meets committers that are about
+            // to back off, so it waits for them instead of failing
+            while !self.mem.lock_addr(addr) {
+                if !self.is_irrevocable {
+                    self.conflict = Some(self.mem.conflict(AbortCause::LockWriteSet, addr));
                     return false;
                 }
-            } else if !self.mem.test_not_modify(addr, self.read_ver) {
+                std::hint::spin_loop();
+            }
+            self.mem.set_owner_karma(addr, self.karma);
+            self.locked.push(addr);
+        }
+        true
+    }
+
+    fn validate_read_set(&mut self) -> bool {
+        for addr in self.read_set.iter() {
+            let conflict = if self.write_set.contains(addr) {
+                // the stripe is locked by this transaction, which overwrote
+                // the karma of the one that committed to it
+                (self.mem.get_addr_ver(addr) > self.read_ver).then_some(Conflict {
+                    cause: AbortCause::ValidateReadSet,
+                    addr,
+                    enemy: None,
+                })
+            } else {
+                (!self.mem.test_not_modify(addr, self.read_ver))
+                    .then(|| self.mem.conflict(AbortCause::ValidateReadSet, addr))
+            };
+
+            if conflict.is_some() {
+                self.conflict = conflict;
                 return false;
             }
         }
@@ -221,16 +497,14 @@ impl<'a> WriteTrans<'a> {
     }
 
     fn commit(&mut self, ver: u64) {
-        for (&addr, val) in &self.write_set {
-            for (dst, src) in self.mem.mem[addr..addr + STRIPE_SIZE].iter_mut().zip(val) {
-                *dst = *src;
-            }
+        for (addr, val) in self.write_set.iter() {
+            self.mem.write_stripe(addr, val);
         }
 
         fence(Ordering::Release);
 
-        for &addr in self.write_set.keys() {
-            let idx = addr >> Memory::SHIFT_SIZE;
+        for (addr, _) in self.write_set.iter() {
+            let idx = addr >> self.mem.shift_size;
             self.mem.lock_ver[idx].store(ver, Ordering::Relaxed);
         }
 
@@ -238,83 +512,446 @@ impl<'a> WriteTrans<'a> {
     }
 }
 
-pub enum STMResult<T> {
-    Ok(T),
-    Retry,
-    Abort,
+enum Attempt<R> {
+    Done(Result<R, STMError>),
+    Retry {
+        read_set: ReadSet,
+        read_ver: u64,
+        abort_hooks: Vec<Hook>,
+    },
+    Conflict {
+        conflict: Option<Conflict>,
+        abort_hooks: Vec<Hook>,
+    },
+    // an irrevocable transaction was running; f has to run again once it is
+    // done, and the hooks of this attempt are discarded
+    Irrevocable,
 }
 
-pub struct STM {
-    mem: UnsafeCell<Memory>,
+enum Commit {
+    Done,
+    Conflict,
+    Irrevocable,
+}
+
+thread_local! {
+    // address of the STM whose irrevocable transaction this thread is running
+    static IRREVOCABLE: Cell<usize> = const { Cell::new(0) };
 }
 
-unsafe impl Sync for STM {}
-unsafe impl Send for STM {}
+// clears the flag when the irrevocable transaction ends, even by a panic
+struct IrrevocableGuard<'a> {
+    stm: &'a STM,
+    _lock: MutexGuard<'a, ()>,
+}
+
+impl<'a> Drop for IrrevocableGuard<'a> {
+    fn drop(&mut self) {
+        self.stm.irrevocable.store(false, Ordering::Relaxed);
+        IRREVOCABLE.with(|stm| stm.set(0));
+
+        let wakers = mem::take(&mut *self.stm.irrevocable_wakers.lock().unwrap());
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+pub struct STM {
+    mem: Memory,
+    brk: AtomicUsize,
+    waiters: AtomicUsize,
+    retry_lock: Mutex<Wakers>,
+    retry_cond: Condvar,
+    contention_manager: Box<dyn ContentionManager>,
+    stats: Option<Counters>,
+    // set while an irrevocable transaction runs; committers only read it, and
+    // wait on irrevocable_lock, or park in irrevocable_wakers if they are
+    // async tasks, when they find it set
+    irrevocable: AtomicBool,
+    irrevocable_lock: Mutex<()>,
+    irrevocable_wakers: Mutex<Vec<Waker>>,
+}
 
 impl STM {
     pub fn new() -> Self {
+        Self::with_size(DEFAULT_MEM_SIZE, DEFAULT_STRIPE_SIZE)
+    }
+
+    pub fn with_size(mem_size: usize, stripe_size: usize) -> Self {
         Self {
-            mem: UnsafeCell::new(Memory::new()),
+            mem: Memory::with_size(mem_size, stripe_size),
+            brk: AtomicUsize::new(0),
+            waiters: AtomicUsize::new(0),
+            retry_lock: Mutex::new(Wakers::default()),
+            retry_cond: Condvar::new(),
+            contention_manager: Box::new(Aggressive),
+            stats: None,
+            irrevocable: AtomicBool::new(false),
+            irrevocable_lock: Mutex::new(()),
+            irrevocable_wakers: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn with_stats(mut self) -> Self {
+        self.stats = Some(Counters::new(self.mem.lock_ver.len()));
+        self
+    }
+
+    pub fn stats(&self) -> Option<Stats> {
+        self.stats
+            .as_ref()
+            .map(|stats| stats.snapshot(self.mem.stripe_size))
+    }
+
+    pub fn with_contention_manager(mut self, cm: impl ContentionManager + 'static) -> Self {
+        self.contention_manager = Box::new(cm);
+        self
+    }
+
+    pub fn alloc<T: NoUninit>(&self, init: T) -> TVar<T> {
+        let stripe_size = self.stripe_size();
+        let size = num_stripes::<T>(stripe_size).max(1) * stripe_size;
+        let addr = self.brk.fetch_add(size, Ordering::Relaxed);
+        assert!(addr + size <= self.mem_size(), "tl2: out of memory");
+
+        let tvar = TVar::new(self.mem.id, addr);
+
+        self.write_transaction(|tr| {
+            tr.write(&tvar, init);
+            STMResult::Ok(())
+        });
+
+        tvar
+    }
+
+    pub fn mem_size(&self) -> usize {
+        self.mem.mem_size()
+    }
+
+    pub fn stripe_size(&self) -> usize {
+        self.mem.stripe_size()
+    }
+
+    fn wait_for_commit(&self, read_set: &ReadSet, read_ver: u64) -> bool {
+        if read_set.is_empty() {
+            return false;
+        }
+
+        let mut guard = self.retry_lock.lock().unwrap();
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        fence(Ordering::SeqCst);
+        while !self.mem.is_modified(read_set, read_ver) {
+            guard = self.retry_cond.wait(guard).unwrap();
+        }
+        self.waiters.fetch_sub(1, Ordering::Relaxed);
+
+        true
+    }
+
+    fn notify_commit(&self) {
+        fence(Ordering::SeqCst);
+        if self.waiters.load(Ordering::SeqCst) > 0 {
+            let wakers = {
+                let mut wakers = self.retry_lock.lock().unwrap();
+                self.retry_cond.notify_all();
+                wakers.take_modified(&self.mem, &self.waiters)
+            };
+            for waker in wakers {
+                waker.wake();
+            }
+        }
+    }
+
+    fn record_abort(&self, stat: &mut TransStat, conflict: Option<Conflict>) {
+        if let (Some(stats), Some(c)) = (&self.stats, conflict) {
+            stats.abort(c.cause, c.addr >> self.mem.shift_size);
+        }
+        stat.aborts += 1;
+    }
+
+    // asks the contention manager what to do about a conflict; returns the
+    // stripe to wait for before running again, if any
+    fn resolve(
+        &self,
+        stat: &mut TransStat,
+        conflict: Option<Conflict>,
+    ) -> Result<Option<usize>, STMError> {
+        self.record_abort(stat, conflict);
+        let enemy = conflict.and_then(|c| c.enemy);
+        match self.contention_manager.on_abort(stat, enemy) {
+            Resolution::AbortSelf => Ok(None),
+            Resolution::WaitForEnemy => Ok(conflict.map(|c| c.addr)),
+            Resolution::GiveUp => Err(STMError::RetryLimit {
+                aborts: stat.aborts,
+            }),
+        }
+    }
+
+    fn on_abort(&self, stat: &mut TransStat, conflict: Option<Conflict>) -> Result<(), STMError> {
+        if let Some(addr) = self.resolve(stat, conflict)? {
+            self.mem.wait_for_stripe(addr);
+        }
+        Ok(())
+    }
+
+    fn on_commit(&self, stat: &TransStat) {
+        if let Some(stats) = &self.stats {
+            stats.commit(stat.aborts);
+        }
+    }
+
+    fn on_retry(&self) {
+        if let Some(stats) = &self.stats {
+            stats.retry();
         }
     }
 
     pub fn read_transaction<R>(&self, f: impl Fn(&mut ReadTrans) -> STMResult<R>) -> Option<R> {
+        self.try_read_transaction(f).ok()
+    }
+
+    pub fn try_read_transaction<R>(
+        &self,
+        f: impl Fn(&mut ReadTrans) -> STMResult<R>,
+    ) -> Result<R, STMError> {
+        let mut stat = TransStat::default();
         loop {
-            let mut tr = ReadTrans::new(unsafe { &*self.mem.get() });
+            let mut tr = ReadTrans::new(&self.mem, stat.aborts);
 
-            match f(&mut tr) {
-                STMResult::Abort => return None,
+            let result = f(&mut tr);
+            stat.karma += tr.read_set.len();
+
+            match result {
+                STMResult::Abort => return Err(STMError::Abort),
+                _ if tr.is_abort => (),
                 STMResult::Retry => {
-                    if tr.is_abort {
+                    self.on_retry();
+                    if self.wait_for_commit(&tr.read_set, tr.read_ver) {
                         continue;
                     }
-                    return None;
+                    return Err(STMError::Retry);
                 }
                 STMResult::Ok(val) => {
-                    if tr.is_abort {
-                        continue;
-                    } else {
-                        return Some(val);
-                    }
+                    self.on_commit(&stat);
+                    return Ok(val);
                 }
             }
+
+            let conflict = tr.conflict;
+            drop(tr);
+            self.on_abort(&mut stat, conflict)?;
         }
     }
 
+    pub fn write_transaction<R>(&self, f: impl Fn(&mut WriteTrans) -> STMResult<R>) -> Option<R> {
+        self.try_write_transaction(f).ok()
+    }
+
+    #[deprecated(note = "renamed to `write_transaction`")]
     pub fn write_tansaction<R>(&self, f: impl Fn(&mut WriteTrans) -> STMResult<R>) -> Option<R> {
-        loop {
-            let mut tr = WriteTrans::new(unsafe { &mut *self.mem.get() });
+        self.write_transaction(f)
+    }
 
-            let result = match f(&mut tr) {
-                STMResult::Abort => return None,
-                STMResult::Retry => {
-                    if tr.is_abort {
+    pub fn try_write_transaction<R>(
+        &self,
+        f: impl Fn(&mut WriteTrans) -> STMResult<R>,
+    ) -> Result<R, STMError> {
+        let mut stat = TransStat::default();
+        loop {
+            match self.attempt(&f, &mut stat) {
+                Attempt::Done(result) => return result,
+                Attempt::Retry {
+                    read_set,
+                    read_ver,
+                    abort_hooks,
+                } => {
+                    if self.wait_for_commit(&read_set, read_ver) {
                         continue;
                     }
-                    return None;
+                    run_hooks(abort_hooks);
+                    return Err(STMError::Retry);
                 }
-                STMResult::Ok(val) => {
-                    if tr.is_abort {
-                        continue;
+                Attempt::Conflict {
+                    conflict,
+                    abort_hooks,
+                } => {
+                    if let Err(e) = self.on_abort(&mut stat, conflict) {
+                        run_hooks(abort_hooks);
+                        return Err(e);
                     }
-                    val
                 }
-            };
+                Attempt::Irrevocable => self.wait_irrevocable(),
+            }
+        }
+    }
+
+    /// Runs `f` as a write transaction inside an async task. See
+    /// [`Atomically`](atomically::Atomically) for how it waits.
+    pub fn atomically<R, F>(&self, f: F) -> atomically::Atomically<'_, F>
+    where
+        F: Fn(&mut WriteTrans) -> STMResult<R>,
+    {
+        atomically::Atomically::new(self, f)
+    }
+
+    // runs f once and commits it if possible; waiting and backing off is left
+    // to the caller so that the blocking and the async loop can share this
+    fn attempt<R>(
+        &self,
+        f: &impl Fn(&mut WriteTrans) -> STMResult<R>,
+        stat: &mut TransStat,
+    ) -> Attempt<R> {
+        let mut tr = WriteTrans::new(&self.mem, stat.aborts);
+
+        let result = f(&mut tr);
+        stat.karma += tr.read_set.len() + tr.write_set.len();
+        tr.karma = stat.karma;
+
+        match result {
+            STMResult::Abort => {
+                run_hooks(mem::take(&mut tr.abort_hooks));
+                return Attempt::Done(Err(STMError::Abort));
+            }
+            _ if tr.is_abort => (),
+            STMResult::Retry => {
+                self.on_retry();
+                return Attempt::Retry {
+                    read_set: mem::take(&mut tr.read_set),
+                    read_ver: tr.read_ver,
+                    abort_hooks: mem::take(&mut tr.abort_hooks),
+                };
+            }
+            STMResult::Ok(val) => match self.commit(&mut tr) {
+                Commit::Done => {
+                    let is_read_only = tr.write_set.is_empty();
+                    let hooks = mem::take(&mut tr.commit_hooks);
+                    drop(tr);
+                    if !is_read_only {
+                        self.notify_commit();
+                    }
+                    self.on_commit(stat);
+                    run_hooks(hooks);
+
+                    return Attempt::Done(Ok(val));
+                }
+                Commit::Irrevocable => return Attempt::Irrevocable,
+                Commit::Conflict => (),
+            },
+        }
 
-            if !tr.lock_write_set() {
-                continue;
+        Attempt::Conflict {
+            conflict: tr.conflict,
+            abort_hooks: mem::take(&mut tr.abort_hooks),
+        }
+    }
+
+    /// Runs `f` exactly once as an irrevocable transaction. While it runs,
+    /// every other write transaction waits before committing, so it never
+    /// conflicts and `f` may perform I/O directly. Returning `Retry` cannot
+    /// block here and fails with `STMError::Retry`.
+    ///
+    /// `f` must not run another write transaction on the same STM, which
+    /// includes `alloc`; committing it would panic, since it could only wait
+    /// for `f` to finish.
+    pub fn irrevocable<R>(
+        &self,
+        f: impl FnOnce(&mut WriteTrans) -> STMResult<R>,
+    ) -> Result<R, STMError> {
+        let guard = self.begin_irrevocable();
+        let mut tr = WriteTrans::new(&self.mem, 0);
+        tr.is_irrevocable = true;
+
+        let e = match f(&mut tr) {
+            STMResult::Ok(val) => {
+                tr.lock_write_set();
+                tr.commit(1 + self.mem.inc_global_clock());
+
+                let hooks = mem::take(&mut tr.commit_hooks);
+                drop(tr);
+                drop(guard);
+                self.notify_commit();
+                self.on_commit(&TransStat::default());
+                run_hooks(hooks);
+
+                return Ok(val);
             }
+            STMResult::Retry => STMError::Retry,
+            STMResult::Abort => STMError::Abort,
+        };
+
+        drop(guard);
+        run_hooks(mem::take(&mut tr.abort_hooks));
+        Err(e)
+    }
 
-            let ver = 1 + tr.mem.inc_global_clock();
+    fn is_irrevocable_thread(&self) -> bool {
+        IRREVOCABLE.with(|stm| stm.get() == self as *const STM as usize)
+    }
+
+    fn begin_irrevocable(&self) -> IrrevocableGuard<'_> {
+        assert!(
+            !self.is_irrevocable_thread(),
+            "tl2: irrevocable transaction started inside another one"
+        );
+
+        let lock = self
+            .irrevocable_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        self.irrevocable.store(true, Ordering::Relaxed);
+
+        // pairs with the fence in commit: a committer that missed the flag
+        // already holds its stripe locks, so waiting for every lock to be
+        // released waits for all of those commits to finish
+        fence(Ordering::SeqCst);
+        self.mem.wait_for_unlock();
 
-            if tr.read_ver + 1 != ver && !tr.validate_read_set() {
-                continue;
+        IRREVOCABLE.with(|stm| stm.set(self as *const STM as usize));
+        IrrevocableGuard {
+            stm: self,
+            _lock: lock,
+        }
+    }
+
+    fn wait_irrevocable(&self) {
+        assert!(
+            !self.is_irrevocable_thread(),
+            "tl2: write transaction committed inside an irrevocable one"
+        );
+        drop(
+            self.irrevocable_lock
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        );
+    }
+
+    fn commit(&self, tr: &mut WriteTrans) -> Commit {
+        // every load was checked against read_ver, so the reads already form
+        // a consistent snapshot and there is nothing to publish
+        if tr.write_set.is_empty() {
+            return Commit::Done;
+        }
+
+        if tr.lock_write_set() {
+            fence(Ordering::SeqCst);
+            if self.irrevocable.load(Ordering::Relaxed) {
+                // back off without writing anything; the caller runs the
+                // transaction again once the irrevocable one is done
+                tr.unlock();
+                return Commit::Irrevocable;
             }
 
-            tr.commit(ver);
+            let ver = 1 + self.mem.inc_global_clock();
 
-            return Some(result);
+            if tr.read_ver + 1 == ver || tr.validate_read_set() {
+                tr.commit(ver);
+                return Commit::Done;
+            }
         }
+
+        tr.unlock();
+        Commit::Conflict
     }
 }
 
@@ -323,3 +960,20 @@ impl Default for STM {
         Self::new()
     }
 }
+
+impl Stm for STM {
+    type ReadTrans<'a> = ReadTrans<'a>;
+    type WriteTrans<'a> = WriteTrans<'a>;
+
+    fn alloc<T: NoUninit>(&self, init: T) -> TVar<T> {
+        STM::alloc(self, init)
+    }
+
+    fn read_transaction<R>(&self, f: impl Fn(&mut ReadTrans) -> STMResult<R>) -> Option<R> {
+        STM::read_transaction(self, f)
+    }
+
+    fn write_transaction<R>(&self, f: impl Fn(&mut WriteTrans) -> STMResult<R>) -> Option<R> {
+        STM::write_transaction(self, f)
+    }
+}
@@ -0,0 +1,184 @@
+use std::{
+    future::Future,
+    mem,
+    pin::Pin,
+    sync::atomic::{fence, AtomicUsize, Ordering},
+    task::{Context, Poll, Waker},
+};
+
+use super::{
+    contention::TransStat, run_hooks, sets::ReadSet, Attempt, Memory, STMError, STMResult,
+    WriteTrans, STM,
+};
+
+struct AsyncWaiter {
+    id: u64,
+    read_set: ReadSet,
+    read_ver: u64,
+    waker: Waker,
+}
+
+/// Tasks suspended on `Retry`, guarded by `STM::retry_lock`.
+#[derive(Default)]
+pub(super) struct Wakers {
+    next_id: u64,
+    waiters: Vec<AsyncWaiter>,
+}
+
+impl Wakers {
+    /// Removes the tasks whose read set has been modified and returns their
+    /// wakers, which the caller invokes after releasing the lock.
+    pub(super) fn take_modified(&mut self, mem: &Memory, count: &AtomicUsize) -> Vec<Waker> {
+        let (modified, waiting) = mem::take(&mut self.waiters)
+            .into_iter()
+            .partition::<Vec<_>, _>(|w| mem.is_modified(&w.read_set, w.read_ver));
+        self.waiters = waiting;
+        count.fetch_sub(modified.len(), Ordering::Relaxed);
+        modified.into_iter().map(|w| w.waker).collect()
+    }
+}
+
+/// Future returned by [`STM::atomically`].
+///
+/// A transaction returning `Retry` parks the task until one of the stripes
+/// it read is committed to, and a commit that meets an irrevocable
+/// transaction parks it until that one is done, instead of blocking the
+/// executor thread. A conflict is passed to the contention manager like in
+/// [`STM::try_write_transaction`]; when it says to wait for the enemy, the
+/// task yields until the stripe is unlocked.
+pub struct Atomically<'a, F> {
+    stm: &'a STM,
+    f: F,
+    stat: TransStat,
+    waiter: Option<u64>,
+    // stripe held by the enemy the contention manager chose to wait for
+    enemy: Option<usize>,
+}
+
+impl<'a, F> Unpin for Atomically<'a, F> {}
+
+impl<'a, F> Atomically<'a, F> {
+    pub(super) fn new(stm: &'a STM, f: F) -> Self {
+        Self {
+            stm,
+            f,
+            stat: TransStat::default(),
+            waiter: None,
+            enemy: None,
+        }
+    }
+
+    // returns false if the irrevocable transaction ended before the task
+    // could sleep
+    fn register_irrevocable(&self, waker: &Waker) -> bool {
+        assert!(
+            !self.stm.is_irrevocable_thread(),
+            "tl2: write transaction committed inside an irrevocable one"
+        );
+
+        // the guard clears the flag before it takes the lock to wake the tasks
+        let mut wakers = self.stm.irrevocable_wakers.lock().unwrap();
+        if !self.stm.irrevocable.load(Ordering::Relaxed) {
+            return false;
+        }
+        wakers.push(waker.clone());
+        true
+    }
+
+    // returns None if the read set was modified before the task could sleep
+    fn register(&mut self, read_set: ReadSet, read_ver: u64, waker: &Waker) -> Option<u64> {
+        let mut wakers = self.stm.retry_lock.lock().unwrap();
+        self.stm.waiters.fetch_add(1, Ordering::SeqCst);
+        fence(Ordering::SeqCst);
+
+        if self.stm.mem.is_modified(&read_set, read_ver) {
+            self.stm.waiters.fetch_sub(1, Ordering::Relaxed);
+            return None;
+        }
+
+        let id = wakers.next_id;
+        wakers.next_id += 1;
+        wakers.waiters.push(AsyncWaiter {
+            id,
+            read_set,
+            read_ver,
+            waker: waker.clone(),
+        });
+        Some(id)
+    }
+
+    fn deregister(&mut self) {
+        if let Some(id) = self.waiter.take() {
+            let mut wakers = self.stm.retry_lock.lock().unwrap();
+            if let Some(i) = wakers.waiters.iter().position(|w| w.id == id) {
+                wakers.waiters.swap_remove(i);
+                self.stm.waiters.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl<'a, F, R> Future for Atomically<'a, F>
+where
+    F: Fn(&mut WriteTrans) -> STMResult<R>,
+{
+    type Output = Result<R, STMError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        this.deregister();
+
+        if let Some(addr) = this.enemy {
+            if this.stm.mem.is_locked(addr) {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            this.enemy = None;
+        }
+
+        loop {
+            match this.stm.attempt(&this.f, &mut this.stat) {
+                Attempt::Done(result) => return Poll::Ready(result),
+                Attempt::Retry {
+                    read_set,
+                    read_ver,
+                    abort_hooks,
+                } => {
+                    if read_set.is_empty() {
+                        run_hooks(abort_hooks);
+                        return Poll::Ready(Err(STMError::Retry));
+                    }
+                    if let Some(id) = this.register(read_set, read_ver, cx.waker()) {
+                        this.waiter = Some(id);
+                        return Poll::Pending;
+                    }
+                }
+                Attempt::Conflict {
+                    conflict,
+                    abort_hooks,
+                } => match this.stm.resolve(&mut this.stat, conflict) {
+                    Ok(enemy) => {
+                        this.enemy = enemy;
+                        cx.waker().wake_by_ref();
+                        return Poll::Pending;
+                    }
+                    Err(e) => {
+                        run_hooks(abort_hooks);
+                        return Poll::Ready(Err(e));
+                    }
+                },
+                Attempt::Irrevocable => {
+                    if this.register_irrevocable(cx.waker()) {
+                        return Poll::Pending;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a, F> Drop for Atomically<'a, F> {
+    fn drop(&mut self) {
+        self.deregister();
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash},
+};
+
+use crate::stm::{NoUninit, Stm, TVar, Transaction, WriteTransaction};
+
+pub struct TCounter {
+    value: TVar<i64>,
+}
+
+impl TCounter {
+    pub fn new(stm: &impl Stm, init: i64) -> Self {
+        Self {
+            value: stm.alloc(init),
+        }
+    }
+
+    pub fn get(&self, tr: &mut impl Transaction) -> Option<i64> {
+        tr.read(&self.value)
+    }
+
+    pub fn set(&self, tr: &mut impl WriteTransaction, val: i64) {
+        tr.write(&self.value, val);
+    }
+
+    pub fn add(&self, tr: &mut impl WriteTransaction, delta: i64) -> Option<i64> {
+        let val = tr.read(&self.value)? + delta;
+        tr.write(&self.value, val);
+        Some(val)
+    }
+}
+
+pub struct TQueue<T> {
+    head: TVar<usize>,
+    len: TVar<usize>,
+    slots: Vec<TVar<T>>,
+}
+
+impl<T: NoUninit + Default> TQueue<T> {
+    pub fn new(stm: &impl Stm, capacity: usize) -> Self {
+        assert!(capacity > 0);
+
+        Self {
+            head: stm.alloc(0),
+            len: stm.alloc(0),
+            slots: (0..capacity).map(|_| stm.alloc(T::default())).collect(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn len(&self, tr: &mut impl Transaction) -> Option<usize> {
+        tr.read(&self.len)
+    }
+
+    pub fn is_empty(&self, tr: &mut impl Transaction) -> Option<bool> {
+        Some(self.len(tr)? == 0)
+    }
+
+    pub fn push(&self, tr: &mut impl WriteTransaction, val: T) -> Option<bool> {
+        let len = tr.read(&self.len)?;
+        if len == self.capacity() {
+            return Some(false);
+        }
+
+        let head = tr.read(&self.head)?;
+        tr.write(&self.slots[(head + len) % self.capacity()], val);
+        tr.write(&self.len, len + 1);
+        Some(true)
+    }
+
+    pub fn pop(&self, tr: &mut impl WriteTransaction) -> Option<Option<T>> {
+        let len = tr.read(&self.len)?;
+        if len == 0 {
+            return Some(None);
+        }
+
+        let head = tr.read(&self.head)?;
+        let val = tr.read(&self.slots[head])?;
+        tr.write(&self.head, (head + 1) % self.capacity());
+        tr.write(&self.len, len - 1);
+        Some(Some(val))
+    }
+}
+
+struct Entry<K, V> {
+    used: TVar<bool>,
+    key: TVar<K>,
+    val: TVar<V>,
+}
+
+pub struct THashMap<K, V> {
+    buckets: Vec<Vec<Entry<K, V>>>,
+    hasher: RandomState,
+}
+
+impl<K, V> THashMap<K, V>
+where
+    K: NoUninit + Default + Eq + Hash,
+    V: NoUninit + Default,
+{
+    pub fn new(stm: &impl Stm, num_buckets: usize, bucket_capacity: usize) -> Self {
+        assert!(num_buckets > 0 && bucket_capacity > 0);
+
+        let buckets = (0..num_buckets)
+            .map(|_| {
+                (0..bucket_capacity)
+                    .map(|_| Entry {
+                        used: stm.alloc(false),
+                        key: stm.alloc(K::default()),
+                        val: stm.alloc(V::default()),
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            buckets,
+            hasher: RandomState::new(),
+        }
+    }
+
+    fn bucket(&self, key: &K) -> &[Entry<K, V>] {
+        let hash = self.hasher.hash_one(key);
+        &self.buckets[hash as usize % self.buckets.len()]
+    }
+
+    fn find(&self, tr: &mut impl Transaction, key: &K) -> Option<Option<&Entry<K, V>>> {
+        for entry in self.bucket(key) {
+            if tr.read(&entry.used)? && tr.read(&entry.key)? == *key {
+                return Some(Some(entry));
+            }
+        }
+        Some(None)
+    }
+
+    pub fn get(&self, tr: &mut impl Transaction, key: &K) -> Option<Option<V>> {
+        match self.find(tr, key)? {
+            Some(entry) => Some(Some(tr.read(&entry.val)?)),
+            None => Some(None),
+        }
+    }
+
+    pub fn contains_key(&self, tr: &mut impl Transaction, key: &K) -> Option<bool> {
+        Some(self.find(tr, key)?.is_some())
+    }
+
+    pub fn insert(&self, tr: &mut impl WriteTransaction, key: K, val: V) -> Option<bool> {
+        if let Some(entry) = self.find(tr, &key)? {
+            tr.write(&entry.val, val);
+            return Some(true);
+        }
+
+        for entry in self.bucket(&key) {
+            if !tr.read(&entry.used)? {
+                tr.write(&entry.used, true);
+                tr.write(&entry.key, key);
+                tr.write(&entry.val, val);
+                return Some(true);
+            }
+        }
+
+        Some(false)
+    }
+
+    pub fn remove(&self, tr: &mut impl WriteTransaction, key: &K) -> Option<Option<V>> {
+        match self.find(tr, key)? {
+            Some(entry) => {
+                let val = tr.read(&entry.val)?;
+                tr.write(&entry.used, false);
+                Some(Some(val))
+            }
+            None => Some(None),
+        }
+    }
+}
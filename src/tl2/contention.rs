@@ -0,0 +1,141 @@
+use std::{
+    cell::Cell,
+    thread,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TransStat {
+    pub aborts: usize,
+    pub karma: usize,
+}
+
+/// What a transaction does after an attempt failed on a conflict.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Runs the transaction again.
+    AbortSelf,
+    /// Waits until the enemy has released the conflicting stripe, then runs
+    /// the transaction again.
+    WaitForEnemy,
+    /// Fails the transaction with `STMError::RetryLimit`.
+    GiveUp,
+}
+
+pub trait ContentionManager: Send + Sync {
+    /// `enemy` is the karma of the transaction that locked or last wrote the
+    /// conflicting stripe, if it is known.
+    fn on_abort(&self, stat: &TransStat, enemy: Option<usize>) -> Resolution;
+}
+
+pub struct Aggressive;
+
+impl ContentionManager for Aggressive {
+    fn on_abort(&self, _stat: &TransStat, _enemy: Option<usize>) -> Resolution {
+        Resolution::AbortSelf
+    }
+}
+
+pub struct ExponentialBackoff {
+    pub min: Duration,
+    pub max: Duration,
+}
+
+impl Default for ExponentialBackoff {
+    fn default() -> Self {
+        Self {
+            min: Duration::from_micros(1),
+            max: Duration::from_millis(1),
+        }
+    }
+}
+
+impl ContentionManager for ExponentialBackoff {
+    fn on_abort(&self, stat: &TransStat, _enemy: Option<usize>) -> Resolution {
+        let shift = stat.aborts.min(31) as u32;
+        let limit = self.min.saturating_mul(1 << shift).min(self.max);
+        pause(jitter(limit));
+        Resolution::AbortSelf
+    }
+}
+
+/// Karma is the number of stripes a transaction accessed, summed over all of
+/// its attempts. The side of a conflict with more karma waits for its enemy
+/// and runs again at once; the other one backs off, the longer the less karma
+/// it has.
+pub struct Karma {
+    pub max: Duration,
+}
+
+impl Default for Karma {
+    fn default() -> Self {
+        Self {
+            max: Duration::from_micros(100),
+        }
+    }
+}
+
+impl ContentionManager for Karma {
+    fn on_abort(&self, stat: &TransStat, enemy: Option<usize>) -> Resolution {
+        match enemy {
+            Some(enemy) if stat.karma > enemy => Resolution::WaitForEnemy,
+            _ => {
+                let karma = stat.karma.min(u32::MAX as usize) as u32;
+                pause(jitter(self.max / (1 + karma)));
+                Resolution::AbortSelf
+            }
+        }
+    }
+}
+
+pub struct MaxRetries<C> {
+    pub inner: C,
+    pub max: usize,
+}
+
+impl<C: ContentionManager> ContentionManager for MaxRetries<C> {
+    fn on_abort(&self, stat: &TransStat, enemy: Option<usize>) -> Resolution {
+        if stat.aborts < self.max {
+            self.inner.on_abort(stat, enemy)
+        } else {
+            Resolution::GiveUp
+        }
+    }
+}
+
+fn jitter(limit: Duration) -> Duration {
+    thread_local! {
+        static SEED: Cell<u64> = const { Cell::new(0) };
+    }
+
+    let nanos = limit.as_nanos() as u64;
+    if nanos == 0 {
+        return limit;
+    }
+
+    let r = SEED.with(|seed| {
+        let mut x = seed.get();
+        if x == 0 {
+            x = seed as *const _ as u64 | 1;
+        }
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        seed.set(x);
+        x
+    });
+
+    Duration::from_nanos(nanos / 2 + r % (nanos / 2 + 1))
+}
+
+fn pause(d: Duration) {
+    if d >= Duration::from_micros(50) {
+        thread::sleep(d);
+        return;
+    }
+
+    let deadline = Instant::now() + d;
+    while Instant::now() < deadline {
+        std::hint::spin_loop();
+    }
+}
@@ -0,0 +1,141 @@
+// Per-transaction read and write sets. Both start without any heap
+// allocation, and a 64-bit bloom filter answers most membership queries
+// without touching the vectors.
+
+use std::collections::HashSet;
+
+// the bloom filter saturates at a few dozen addresses, so larger read sets
+// are indexed by a hash set instead
+const MAX_SMALL_READ_SET: usize = 16;
+
+#[derive(Clone, Copy, Default)]
+struct Bloom(u64);
+
+impl Bloom {
+    fn bits(addr: usize) -> u64 {
+        let h = (addr as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        1 << (h >> 58) | 1 << ((h >> 52) & 63)
+    }
+
+    fn insert(&mut self, addr: usize) {
+        self.0 |= Self::bits(addr);
+    }
+
+    fn may_contain(&self, addr: usize) -> bool {
+        let bits = Self::bits(addr);
+        self.0 & bits == bits
+    }
+}
+
+#[derive(Default)]
+pub(super) struct ReadSet {
+    addrs: Vec<usize>,
+    bloom: Bloom,
+    index: Option<HashSet<usize>>,
+}
+
+impl ReadSet {
+    pub(super) fn insert(&mut self, addr: usize) {
+        if let Some(index) = &mut self.index {
+            if index.insert(addr) {
+                self.addrs.push(addr);
+            }
+            return;
+        }
+
+        if self.bloom.may_contain(addr) && self.addrs.contains(&addr) {
+            return;
+        }
+        self.bloom.insert(addr);
+        self.addrs.push(addr);
+
+        if self.addrs.len() > MAX_SMALL_READ_SET {
+            self.index = Some(self.addrs.iter().copied().collect());
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.addrs.len()
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.addrs.is_empty()
+    }
+
+    pub(super) fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.addrs.iter().copied()
+    }
+}
+
+/// Stripes to be written, kept sorted by address so that commits lock them
+/// in a global order. Their values live back to back in one buffer.
+#[derive(Clone)]
+pub(super) struct WriteSet {
+    entries: Vec<(usize, usize)>,
+    data: Vec<u8>,
+    stripe_size: usize,
+    bloom: Bloom,
+}
+
+impl WriteSet {
+    pub(super) fn new(stripe_size: usize) -> Self {
+        Self {
+            entries: Vec::new(),
+            data: Vec::new(),
+            stripe_size,
+            bloom: Bloom::default(),
+        }
+    }
+
+    fn find(&self, addr: usize) -> Result<usize, usize> {
+        self.entries.binary_search_by_key(&addr, |&(a, _)| a)
+    }
+
+    /// Stores `val` for the stripe at `addr`, zero-filling a short value.
+    pub(super) fn insert(&mut self, addr: usize, val: &[u8]) {
+        assert!(val.len() <= self.stripe_size);
+
+        let offset = match self.find(addr) {
+            Ok(i) => self.entries[i].1,
+            Err(i) => {
+                let offset = self.data.len();
+                self.data.resize(offset + self.stripe_size, 0);
+                self.entries.insert(i, (addr, offset));
+                self.bloom.insert(addr);
+                offset
+            }
+        };
+
+        let stripe = &mut self.data[offset..offset + self.stripe_size];
+        stripe[..val.len()].copy_from_slice(val);
+        stripe[val.len()..].fill(0);
+    }
+
+    pub(super) fn get(&self, addr: usize) -> Option<&[u8]> {
+        if !self.bloom.may_contain(addr) {
+            return None;
+        }
+        let i = self.find(addr).ok()?;
+        let offset = self.entries[i].1;
+        Some(&self.data[offset..offset + self.stripe_size])
+    }
+
+    pub(super) fn contains(&self, addr: usize) -> bool {
+        self.get(addr).is_some()
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterates over `(addr, value)` in ascending address order.
+    pub(super) fn iter(&self) -> impl Iterator<Item = (usize, &[u8])> + '_ {
+        self.entries
+            .iter()
+            .map(move |&(addr, offset)| (addr, &self.data[offset..offset + self.stripe_size]))
+    }
+}
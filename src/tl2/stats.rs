@@ -0,0 +1,101 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const NUM_HOT_STRIPES: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AbortCause {
+    Load,
+    LockWriteSet,
+    ValidateReadSet,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub commits: u64,
+    pub load_aborts: u64,
+    pub lock_aborts: u64,
+    pub validate_aborts: u64,
+    pub retries: u64,
+    pub max_aborts_per_transaction: u64,
+    pub hot_stripes: Vec<(usize, u64)>,
+}
+
+impl Stats {
+    pub fn aborts(&self) -> u64 {
+        self.load_aborts + self.lock_aborts + self.validate_aborts
+    }
+
+    pub fn aborts_per_commit(&self) -> f64 {
+        if self.commits == 0 {
+            0.0
+        } else {
+            self.aborts() as f64 / self.commits as f64
+        }
+    }
+}
+
+pub(super) struct Counters {
+    commits: AtomicU64,
+    load_aborts: AtomicU64,
+    lock_aborts: AtomicU64,
+    validate_aborts: AtomicU64,
+    retries: AtomicU64,
+    max_aborts_per_transaction: AtomicU64,
+    conflicts: Vec<AtomicU64>,
+}
+
+impl Counters {
+    pub(super) fn new(num_stripes: usize) -> Self {
+        Self {
+            commits: AtomicU64::new(0),
+            load_aborts: AtomicU64::new(0),
+            lock_aborts: AtomicU64::new(0),
+            validate_aborts: AtomicU64::new(0),
+            retries: AtomicU64::new(0),
+            max_aborts_per_transaction: AtomicU64::new(0),
+            conflicts: (0..num_stripes).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    pub(super) fn commit(&self, aborts: usize) {
+        self.commits.fetch_add(1, Ordering::Relaxed);
+        self.max_aborts_per_transaction
+            .fetch_max(aborts as u64, Ordering::Relaxed);
+    }
+
+    pub(super) fn abort(&self, cause: AbortCause, stripe: usize) {
+        let counter = match cause {
+            AbortCause::Load => &self.load_aborts,
+            AbortCause::LockWriteSet => &self.lock_aborts,
+            AbortCause::ValidateReadSet => &self.validate_aborts,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        self.conflicts[stripe].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn retry(&self) {
+        self.retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn snapshot(&self, stripe_size: usize) -> Stats {
+        let mut hot_stripes: Vec<_> = self
+            .conflicts
+            .iter()
+            .enumerate()
+            .map(|(idx, n)| (idx * stripe_size, n.load(Ordering::Relaxed)))
+            .filter(|&(_, n)| n > 0)
+            .collect();
+        hot_stripes.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        hot_stripes.truncate(NUM_HOT_STRIPES);
+
+        Stats {
+            commits: self.commits.load(Ordering::Relaxed),
+            load_aborts: self.load_aborts.load(Ordering::Relaxed),
+            lock_aborts: self.lock_aborts.load(Ordering::Relaxed),
+            validate_aborts: self.validate_aborts.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            max_aborts_per_transaction: self.max_aborts_per_transaction.load(Ordering::Relaxed),
+            hot_stripes,
+        }
+    }
+}
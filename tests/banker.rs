@@ -0,0 +1,186 @@
+use std::{thread, time::Duration};
+
+use oreilly_concurrent::banker::{
+    safe_sequence, safe_sequence_scan, Banker, BankerError, DynBanker, XorShift,
+};
+
+#[test]
+fn banker_refuses_unsafe_take() {
+    let banker = Banker::<2, 2>::new([1, 1], [[1, 1], [1, 1]]).unwrap();
+    assert_eq!(banker.take(0, 0), Ok(()));
+    assert_eq!(banker.take(1, 1), Err(BankerError::Unsafe));
+    assert_eq!(banker.take(0, 1), Ok(()));
+    assert_eq!(banker.take(0, 1), Err(BankerError::ExceedsMax));
+    assert_eq!(banker.take(1, 1), Err(BankerError::Unavailable));
+    assert_eq!(banker.take(2, 0), Err(BankerError::InvalidProcess));
+    assert_eq!(banker.take(1, 2), Err(BankerError::InvalidResource));
+
+    banker.release(0, 0);
+    banker.release(0, 1);
+    assert_eq!(banker.take(1, 1), Ok(()));
+}
+
+#[test]
+fn banker_take_blocking_waits_for_release() {
+    const NUM_LOOP: usize = 1_000;
+
+    let banker = Banker::<2, 2>::new([1, 1], [[1, 1], [1, 1]]).unwrap();
+    let v: Vec<_> = (0..2)
+        .map(|id| {
+            let banker0 = banker.clone();
+            thread::spawn(move || {
+                // opposite orders would deadlock without the safety check
+                let (first, second) = if id == 0 { (0, 1) } else { (1, 0) };
+                for _ in 0..NUM_LOOP {
+                    banker0.take_blocking(id, first).unwrap();
+                    banker0.take_blocking(id, second).unwrap();
+                    banker0.release(id, first);
+                    banker0.release(id, second);
+                }
+            })
+        })
+        .collect();
+
+    for t in v {
+        t.join().unwrap();
+    }
+
+    // a request beyond the maximum claim fails instead of blocking forever
+    assert_eq!(banker.take(0, 0), Ok(()));
+    assert_eq!(banker.take_blocking(0, 0), Err(BankerError::ExceedsMax));
+}
+
+#[test]
+fn banker_take_timeout() {
+    let banker = Banker::<1, 2>::new([1], [[1], [1]]).unwrap();
+    assert_eq!(banker.take(0, 0), Ok(()));
+    assert_eq!(
+        banker.take_timeout(1, 0, Duration::from_millis(10)),
+        Err(BankerError::Unavailable)
+    );
+
+    let banker0 = banker.clone();
+    let t = thread::spawn(move || banker0.take_timeout(1, 0, Duration::from_secs(10)));
+    thread::sleep(Duration::from_millis(50));
+    banker.release(0, 0);
+    assert_eq!(t.join().unwrap(), Ok(()));
+}
+
+#[test]
+fn banker_request_is_all_or_nothing() {
+    let banker = Banker::<3, 2>::new([3, 2, 2], [[2, 2, 1], [3, 1, 2]]).unwrap();
+
+    assert_eq!(banker.request(0, &[3, 0, 0]), Err(BankerError::ExceedsMax));
+    assert_eq!(banker.request(1, &[1, 0, 2]), Ok(()));
+    assert_eq!(banker.request(0, &[0, 0, 1]), Err(BankerError::Unavailable));
+    // would leave nobody able to finish
+    assert_eq!(banker.request(0, &[2, 1, 0]), Err(BankerError::Unsafe));
+    assert_eq!(banker.request(0, &[0, 1, 0]), Ok(()));
+
+    // nothing was granted by the refused requests
+    banker.release_all(1);
+    assert_eq!(banker.request(0, &[2, 1, 1]), Ok(()));
+    assert_eq!(banker.request(1, &[1, 0, 1]), Ok(()));
+    assert_eq!(banker.request(1, &[0, 0, 1]), Err(BankerError::Unavailable));
+
+    banker.release_all(0);
+    banker.release_all(1);
+    assert_eq!(banker.request(1, &[3, 1, 2]), Ok(()));
+}
+
+#[test]
+fn banker_safe_sequence() {
+    let banker = Banker::<1, 3>::new([10], [[9], [4], [7]]).unwrap();
+    assert_eq!(banker.request(0, &[3]), Ok(()));
+    assert_eq!(banker.request(1, &[2]), Ok(()));
+    assert_eq!(banker.request(2, &[2]), Ok(()));
+
+    // only process 1 can finish with what is left, which then frees enough for
+    // process 2 and finally process 0
+    assert_eq!(banker.safe_sequence(), vec![1, 2, 0]);
+
+    // a claim beyond the total supply could never be met
+    assert!(Banker::<1, 1>::new([1], [[2]]).is_none());
+}
+
+#[test]
+fn safe_sequence_agrees_with_scan() {
+    const NTH: usize = 32;
+    const NRES: usize = 4;
+
+    let mut rng = XorShift(88172645463325252);
+    let mut next = |n: usize| rng.below(n);
+
+    let mut num_safe = 0;
+    for _ in 0..200 {
+        let max: Vec<Vec<_>> = (0..NTH)
+            .map(|_| (0..NRES).map(|_| next(8)).collect())
+            .collect();
+        let allocation: Vec<Vec<_>> = max
+            .iter()
+            .map(|m| m.iter().map(|&m| next(m + 1)).collect())
+            .collect();
+        let available: Vec<_> = (0..NRES).map(|_| next(12)).collect();
+
+        let seq = safe_sequence(&available, &max, &allocation);
+        assert_eq!(
+            seq.is_some(),
+            safe_sequence_scan(&available, &max, &allocation).is_some()
+        );
+
+        // replay the sequence to check that every process really can finish
+        if let Some(seq) = seq {
+            num_safe += 1;
+            let mut work = available.clone();
+            let mut seen = [false; NTH];
+            for i in seq {
+                assert!(!seen[i]);
+                seen[i] = true;
+                for r in 0..NRES {
+                    assert!(max[i][r] - allocation[i][r] <= work[r]);
+                    work[r] += allocation[i][r];
+                }
+            }
+            assert!(seen.iter().all(|s| *s));
+        }
+    }
+
+    // make sure both outcomes were exercised
+    assert!(num_safe > 0 && num_safe < 200);
+}
+
+#[test]
+fn dyn_banker_register_and_deregister() {
+    let banker = DynBanker::new(vec![1, 1]);
+    assert_eq!(banker.register(vec![2, 1]), None);
+    assert_eq!(banker.register(vec![1]), None);
+
+    let p0 = banker.register(vec![1, 1]).unwrap();
+    let p1 = banker.register(vec![1, 1]).unwrap();
+    assert_ne!(p0, p1);
+
+    assert_eq!(banker.take(p0, 0), Ok(()));
+    assert_eq!(banker.take(p1, 1), Err(BankerError::Unsafe));
+    assert_eq!(banker.safe_sequence(), vec![p0, p1]);
+
+    // deregistering returns p0's allocation, which makes p1 safe again
+    banker.deregister(p0);
+    assert_eq!(banker.take(p0, 1), Err(BankerError::InvalidProcess));
+    assert_eq!(banker.take(p1, 1), Ok(()));
+    assert_eq!(banker.safe_sequence(), vec![p1]);
+
+    // the freed slot is reused under a new id, the old one stays invalid,
+    // and the new process sees the shrunken state
+    let p2 = banker.register(vec![1, 1]).unwrap();
+    assert_eq!(p2.slot(), p0.slot());
+    assert_ne!(p2, p0);
+    assert_eq!(banker.take(p0, 0), Err(BankerError::InvalidProcess));
+    banker.deregister(p0);
+    assert_eq!(banker.safe_sequence(), vec![p1, p2]);
+    assert_eq!(banker.take(p1, 0), Ok(()));
+    assert_eq!(banker.take(p2, 0), Err(BankerError::Unavailable));
+
+    banker.deregister(p1);
+    assert_eq!(banker.take(p2, 0), Ok(()));
+    assert_eq!(banker.take(p2, 1), Ok(()));
+}
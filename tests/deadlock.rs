@@ -0,0 +1,114 @@
+use std::{thread, time::Duration};
+
+use oreilly_concurrent::deadlock::{AcquireError, ResourceManager};
+
+fn wait_until(f: impl Fn() -> bool) {
+    while !f() {
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn detect_and_abort_victim() {
+    let manager = ResourceManager::new(vec![1, 1]);
+    manager.acquire(0, 0).unwrap();
+    manager.acquire(1, 1).unwrap();
+    assert!(manager.detect().is_empty());
+
+    let manager0 = manager.clone();
+    let t0 = thread::spawn(move || manager0.acquire(0, 1));
+    wait_until(|| manager.wait_for_graph() == vec![(0, 1)]);
+    assert!(manager.detect().is_empty());
+
+    let manager1 = manager.clone();
+    let t1 = thread::spawn(move || manager1.acquire(1, 0));
+    wait_until(|| !manager.detect().is_empty());
+
+    assert_eq!(manager.detect(), vec![0, 1]);
+    assert_eq!(manager.wait_for_graph(), vec![(0, 1), (1, 0)]);
+    assert_eq!(manager.find_cycle(), Some(vec![0, 1]));
+
+    // only a waiting process can be aborted
+    assert_eq!(manager.resolve(|cycle| cycle[1]), Some(1));
+    assert_eq!(t1.join().unwrap(), Err(AcquireError::Aborted));
+    assert_eq!(t0.join().unwrap(), Ok(()));
+    assert!(!manager.abort(0));
+    assert!(manager.detect().is_empty());
+    assert_eq!(manager.resolve(|_| unreachable!()), None);
+    assert!(!manager.try_acquire(1, 0));
+}
+
+#[test]
+fn process_waiting_on_itself_is_deadlocked() {
+    let manager = ResourceManager::new(vec![1, 0]);
+    assert_eq!(manager.acquire(0, 1), Err(AcquireError::InvalidResource));
+    manager.acquire(0, 0).unwrap();
+
+    let manager0 = manager.clone();
+    let t = thread::spawn(move || manager0.acquire(0, 0));
+    wait_until(|| !manager.detect().is_empty());
+
+    assert_eq!(manager.detect(), vec![0]);
+    assert_eq!(manager.wait_for_graph(), vec![(0, 0)]);
+    assert_eq!(manager.find_cycle(), Some(vec![0]));
+
+    assert!(manager.abort(0));
+    assert_eq!(t.join().unwrap(), Err(AcquireError::Aborted));
+    assert!(manager.try_acquire(1, 0));
+}
+
+#[test]
+fn waiting_on_a_running_process_is_not_a_deadlock() {
+    // two units of resource 0, so process 2 can still finish and free one
+    let manager = ResourceManager::new(vec![2, 1]);
+    manager.acquire(0, 0).unwrap();
+    manager.acquire(1, 1).unwrap();
+    manager.acquire(2, 0).unwrap();
+
+    let manager0 = manager.clone();
+    let t0 = thread::spawn(move || manager0.acquire(0, 1));
+    let manager1 = manager.clone();
+    let t1 = thread::spawn(move || manager1.acquire(1, 0));
+    wait_until(|| manager.wait_for_graph().len() == 3);
+
+    // 1 waits for 0 and 2, 0 waits for 1: a cycle, but 2 is not blocked
+    assert_eq!(manager.wait_for_graph(), vec![(0, 1), (1, 0), (1, 2)]);
+    assert!(manager.detect().is_empty());
+    assert_eq!(manager.find_cycle(), None);
+
+    manager.release_all(2);
+    assert_eq!(t1.join().unwrap(), Ok(()));
+    manager.release_all(1);
+    assert_eq!(t0.join().unwrap(), Ok(()));
+}
+
+#[test]
+fn detector_resolves_deadlock() {
+    const NUM_LOOP: usize = 100;
+
+    let manager = ResourceManager::new(vec![1, 1]);
+    manager.spawn_detector(Duration::from_millis(1), |cycle| {
+        *cycle.iter().max().unwrap()
+    });
+
+    let v: Vec<_> = (0..2)
+        .map(|id| {
+            let manager0 = manager.clone();
+            thread::spawn(move || {
+                let (first, second) = if id == 0 { (0, 1) } else { (1, 0) };
+                let mut n = 0;
+                while n < NUM_LOOP {
+                    manager0.acquire(id, first).unwrap();
+                    if manager0.acquire(id, second).is_ok() {
+                        n += 1;
+                        manager0.release_all(id);
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for t in v {
+        t.join().unwrap();
+    }
+}
@@ -0,0 +1,69 @@
+use std::{sync::Arc, thread};
+
+use oreilly_concurrent::{array_lock::ArrayLock, spin_lock::SpinLock, ticket_lock::TicketLock};
+
+const NUM_LOOP: usize = 1_000;
+const NUM_THREADS: usize = 4;
+
+macro_rules! lock_tests {
+    ($name:ident, $new:expr) => {
+        mod $name {
+            use super::*;
+
+            #[test]
+            fn mutual_exclusion() {
+                let lock = Arc::new($new(0));
+                let v: Vec<_> = (0..NUM_THREADS)
+                    .map(|_| {
+                        let lock0 = lock.clone();
+                        thread::spawn(move || {
+                            for _ in 0..NUM_LOOP {
+                                let mut r = lock0.lock();
+                                *r += 1;
+                            }
+                        })
+                    })
+                    .collect();
+
+                for t in v {
+                    t.join().unwrap();
+                }
+
+                assert_eq!(*lock.lock(), NUM_LOOP * NUM_THREADS);
+            }
+
+            #[test]
+            fn try_lock() {
+                let lock = $new(0);
+
+                let mut guard = lock.try_lock().unwrap();
+                *guard += 1;
+                assert!(lock.try_lock().is_none());
+                drop(guard);
+
+                let guard = lock.try_lock().unwrap();
+                assert_eq!(*guard, 1);
+                drop(guard);
+
+                assert_eq!(*lock.lock(), 1);
+                assert!(lock.try_lock().is_some());
+            }
+        }
+    };
+}
+
+lock_tests!(spin, SpinLock::new);
+lock_tests!(ticket, TicketLock::new);
+lock_tests!(array, |v| ArrayLock::new(v, NUM_THREADS));
+
+static STATIC_LOCK: SpinLock<usize> = SpinLock::new(0);
+
+#[test]
+fn spin_lock_is_locked() {
+    assert!(!STATIC_LOCK.is_locked());
+    let mut guard = STATIC_LOCK.lock();
+    *guard += 1;
+    assert!(STATIC_LOCK.is_locked());
+    drop(guard);
+    assert!(!STATIC_LOCK.is_locked());
+}
@@ -0,0 +1,226 @@
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use oreilly_concurrent::{
+    cache_padded::Packed,
+    clh_lock::{CLHLock, CLHNode},
+    cohort_lock::{CohortLock, CohortNode},
+    mcs_lock::{MCSLock, MCSNode},
+    numa::Topology,
+};
+
+const NUM_LOOP: usize = 10_000;
+const NUM_THREADS: usize = 4;
+
+macro_rules! queue_lock_tests {
+    ($name:ident, $lock:ident, $node:ident) => {
+        mod $name {
+            use super::*;
+
+            #[test]
+            fn mutual_exclusion() {
+                let lock = Arc::new($lock::new(0));
+                let v: Vec<_> = (0..NUM_THREADS)
+                    .map(|_| {
+                        let lock0 = lock.clone();
+                        thread::spawn(move || {
+                            let mut node = $node::new();
+                            for _ in 0..NUM_LOOP {
+                                let mut r = lock0.lock(&mut node);
+                                *r += 1;
+                            }
+                        })
+                    })
+                    .collect();
+
+                for t in v {
+                    t.join().unwrap();
+                }
+
+                let mut node = $node::new();
+                assert_eq!(*lock.lock(&mut node), NUM_LOOP * NUM_THREADS);
+            }
+
+            #[test]
+            fn fifo_order() {
+                let lock = Arc::new($lock::new(Vec::new()));
+                let order = Arc::new(Mutex::new(Vec::new()));
+
+                let mut node = $node::new();
+                let guard = lock.lock(&mut node);
+
+                let v: Vec<_> = (0..NUM_THREADS)
+                    .map(|i| {
+                        let lock0 = lock.clone();
+                        let order0 = order.clone();
+                        let t = thread::spawn(move || {
+                            let mut node = $node::new();
+                            order0.lock().unwrap().push(i);
+                            let mut r = lock0.lock(&mut node);
+                            r.push(i);
+                        });
+                        while order.lock().unwrap().len() <= i {
+                            thread::yield_now();
+                        }
+                        thread::sleep(Duration::from_millis(50));
+                        t
+                    })
+                    .collect();
+
+                drop(guard);
+
+                for t in v {
+                    t.join().unwrap();
+                }
+
+                let expected: Vec<_> = (0..NUM_THREADS).collect();
+                assert_eq!(*lock.lock(&mut node), expected);
+            }
+        }
+    };
+}
+
+queue_lock_tests!(mcs, MCSLock, MCSNode);
+queue_lock_tests!(clh, CLHLock, CLHNode);
+
+#[test]
+fn cohort_lock_across_nodes() {
+    let lock = Arc::new(CohortLock::with_topology(0, Topology::new(vec![0, 1]), 4));
+    let v: Vec<_> = (0..NUM_THREADS)
+        .map(|i| {
+            let lock0 = lock.clone();
+            thread::spawn(move || {
+                let mut node = CohortNode::new();
+                for _ in 0..NUM_LOOP / 10 {
+                    let mut r = lock0.lock_on(i % 2, &mut node);
+                    *r += 1;
+                }
+            })
+        })
+        .collect();
+
+    for t in v {
+        t.join().unwrap();
+    }
+
+    let mut node = CohortNode::new();
+    assert_eq!(*lock.lock(&mut node), NUM_LOOP / 10 * NUM_THREADS);
+}
+
+#[test]
+fn mcs_lock_packed_layout() {
+    let lock = Arc::new(MCSLock::<_, Packed>::with_layout(0));
+    let v: Vec<_> = (0..NUM_THREADS)
+        .map(|_| {
+            let lock0 = lock.clone();
+            thread::spawn(move || {
+                let mut node = MCSNode::with_layout();
+                for _ in 0..NUM_LOOP {
+                    let mut r = lock0.lock(&mut node);
+                    *r += 1;
+                }
+            })
+        })
+        .collect();
+
+    for t in v {
+        t.join().unwrap();
+    }
+
+    let mut node = MCSNode::with_layout();
+    assert_eq!(*lock.lock(&mut node), NUM_LOOP * NUM_THREADS);
+}
+
+#[test]
+fn mcs_lock_timeout() {
+    let lock = Arc::new(MCSLock::new(0));
+
+    let mut node = MCSNode::new();
+    let guard = lock.lock(&mut node);
+
+    let lock0 = lock.clone();
+    let t = thread::spawn(move || {
+        let mut node = MCSNode::new();
+        assert!(lock0
+            .lock_timeout(&mut node, Duration::from_millis(10))
+            .is_none());
+        let mut r = lock0.lock(&mut node);
+        *r += 1;
+    });
+
+    thread::sleep(Duration::from_millis(100));
+    drop(guard);
+    t.join().unwrap();
+
+    assert_eq!(*lock.lock(&mut node), 1);
+}
+
+#[test]
+fn mcs_lock_timeout_contended() {
+    let lock = Arc::new(MCSLock::new(0));
+    let acquired = Arc::new(Mutex::new(0));
+    let v: Vec<_> = (0..NUM_THREADS)
+        .map(|i| {
+            let lock0 = lock.clone();
+            let acquired0 = acquired.clone();
+            thread::spawn(move || {
+                let mut node = MCSNode::new();
+                let mut n = 0;
+                for j in 0..NUM_LOOP / 10 {
+                    if (i + j) % 2 == 0 {
+                        let mut r = lock0.lock(&mut node);
+                        *r += 1;
+                        n += 1;
+                    } else if let Some(mut r) =
+                        lock0.lock_timeout(&mut node, Duration::from_micros(10))
+                    {
+                        *r += 1;
+                        n += 1;
+                    }
+                }
+                *acquired0.lock().unwrap() += n;
+            })
+        })
+        .collect();
+
+    for t in v {
+        t.join().unwrap();
+    }
+
+    let mut node = MCSNode::new();
+    assert_eq!(*lock.lock(&mut node), *acquired.lock().unwrap());
+}
+
+#[test]
+fn mcs_lock_timeout_reused_node() {
+    let lock = Arc::new(MCSLock::new(0));
+    let acquired = Arc::new(Mutex::new(0));
+    let v: Vec<_> = (0..NUM_THREADS)
+        .map(|_| {
+            let lock0 = lock.clone();
+            let acquired0 = acquired.clone();
+            thread::spawn(move || {
+                // one node per thread, reused across every timed attempt
+                let mut node = MCSNode::new();
+                let mut n = 0;
+                for _ in 0..NUM_LOOP / 10 {
+                    if let Some(mut r) = lock0.lock_timeout(&mut node, Duration::from_micros(1)) {
+                        *r += 1;
+                        n += 1;
+                    }
+                }
+                *acquired0.lock().unwrap() += n;
+            })
+        })
+        .collect();
+
+    for t in v {
+        t.join().unwrap();
+    }
+
+    let mut node = MCSNode::new();
+    assert_eq!(*lock.lock(&mut node), *acquired.lock().unwrap());
+}
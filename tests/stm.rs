@@ -0,0 +1,151 @@
+use std::{sync::Arc, thread, time::Duration};
+
+use oreilly_concurrent::{
+    norec,
+    stm::{STMResult, Stm, Transaction, WriteTransaction},
+    tl2::{self, collections::TQueue},
+};
+
+macro_rules! read {
+    ($t:ident, $a:expr) => {
+        opt!($t.read($a))
+    };
+}
+
+macro_rules! opt {
+    ($e:expr) => {
+        match $e {
+            Some(v) => v,
+            None => return STMResult::Retry,
+        }
+    };
+}
+
+fn concurrent_increments<S: Stm + 'static>(stm: S) {
+    const NUM_THREADS: usize = 4;
+    const NUM_LOOP: u64 = if cfg!(miri) { 10 } else { 1000 };
+
+    let stm = Arc::new(stm);
+    let n = stm.alloc(0u64);
+
+    let v: Vec<_> = (0..NUM_THREADS)
+        .map(|_| {
+            let stm0 = stm.clone();
+            thread::spawn(move || {
+                for _ in 0..NUM_LOOP {
+                    stm0.write_transaction(|tr| {
+                        let v = read!(tr, &n);
+                        tr.write(&n, v + 1);
+                        STMResult::Ok(())
+                    })
+                    .unwrap();
+                }
+            })
+        })
+        .collect();
+
+    for t in v {
+        t.join().unwrap();
+    }
+
+    let v = stm
+        .read_transaction(|tr| STMResult::Ok(read!(tr, &n)))
+        .unwrap();
+    assert_eq!(v, NUM_LOOP * NUM_THREADS as u64);
+}
+
+fn retry_blocks_until_commit<S: Stm + 'static>(stm: S) {
+    let stm = Arc::new(stm);
+    let flag = stm.alloc(false);
+
+    let stm0 = stm.clone();
+    let t = thread::spawn(move || {
+        stm0.write_transaction(|tr| {
+            if !read!(tr, &flag) {
+                return STMResult::Retry;
+            }
+            tr.write(&flag, false);
+            STMResult::Ok(())
+        })
+    });
+
+    thread::sleep(Duration::from_millis(50));
+    stm.write_transaction(|tr| {
+        tr.write(&flag, true);
+        STMResult::Ok(())
+    });
+
+    assert_eq!(t.join().unwrap(), Some(()));
+    let v = stm
+        .read_transaction(|tr| STMResult::Ok(read!(tr, &flag)))
+        .unwrap();
+    assert!(!v);
+}
+
+fn queue_is_consistent<S: Stm>(stm: S) {
+    let queue = TQueue::new(&stm, 4);
+
+    stm.write_transaction(|tr| {
+        for i in 0..4u32 {
+            if !opt!(queue.push(tr, i)) {
+                return STMResult::Abort;
+            }
+        }
+        STMResult::Ok(())
+    })
+    .unwrap();
+
+    let (first, len) = stm
+        .write_transaction(|tr| {
+            let first = opt!(queue.pop(tr));
+            assert!(opt!(queue.push(tr, 4)));
+            STMResult::Ok((first, opt!(queue.len(tr))))
+        })
+        .unwrap();
+    assert_eq!(first, Some(0));
+    assert_eq!(len, 4);
+
+    let is_full = stm
+        .write_transaction(|tr| STMResult::Ok(!opt!(queue.push(tr, 5))))
+        .unwrap();
+    assert!(is_full);
+}
+
+fn tvar_of_other_stm_panics<S: Stm>(stm: S, other: S) {
+    let byte = stm.alloc(2u8);
+    other.alloc(false);
+
+    other.read_transaction(|tr| STMResult::Ok(read!(tr, &byte)));
+}
+
+macro_rules! stm_tests {
+    ($name:ident, $stm:expr) => {
+        mod $name {
+            use super::*;
+
+            #[test]
+            fn concurrent_increments() {
+                super::concurrent_increments($stm);
+            }
+
+            #[test]
+            fn retry_blocks_until_commit() {
+                super::retry_blocks_until_commit($stm);
+            }
+
+            #[test]
+            fn queue_is_consistent() {
+                super::queue_is_consistent($stm);
+            }
+
+            #[test]
+            #[should_panic(expected = "did not allocate")]
+            fn tvar_of_other_stm_panics() {
+                super::tvar_of_other_stm_panics($stm, $stm);
+            }
+        }
+    };
+}
+
+stm_tests!(tl2_stm, tl2::STM::new());
+stm_tests!(norec_stm, norec::STM::new());
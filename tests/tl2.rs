@@ -0,0 +1,669 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use oreilly_concurrent::{
+    scheduling::Executor,
+    tl2::{
+        collections::{TCounter, THashMap, TQueue},
+        contention::{Aggressive, ContentionManager, Karma, MaxRetries, Resolution, TransStat},
+        NoUninit, STMError, STMResult, WriteTrans, STM,
+    },
+};
+
+macro_rules! read {
+    ($t:ident, $a:expr) => {
+        opt!($t.read($a))
+    };
+}
+
+macro_rules! opt {
+    ($e:expr) => {
+        match $e {
+            Some(v) => v,
+            None => return STMResult::Retry,
+        }
+    };
+}
+
+// Miri interprets every access to the STM memory, so the tests shrink their
+// sizes under it: `cargo +nightly miri test --test tl2`
+const MEM_SIZE: usize = if cfg!(miri) { 1 << 12 } else { 1 << 16 };
+
+#[test]
+fn configurable_size() {
+    let stm = STM::with_size(MEM_SIZE, 64);
+    assert_eq!(stm.mem_size(), MEM_SIZE);
+    assert_eq!(stm.stripe_size(), 64);
+
+    // fill the memory up to the last stripe
+    let last = (0..stm.mem_size() / 64)
+        .map(|_| stm.alloc([0u8; 64]))
+        .last()
+        .unwrap();
+    assert_eq!(last.addr(), stm.mem_size() - stm.stripe_size());
+
+    stm.write_transaction(|tr| {
+        let mut v = tr.read(&last).unwrap();
+        v[63] = 42;
+        tr.write(&last, v);
+        STMResult::Ok(())
+    })
+    .unwrap();
+
+    let last = last.addr();
+
+    let v = stm
+        .read_transaction(|tr| {
+            let mut buf = [0; 64];
+            tr.load(last, &mut buf).unwrap();
+            STMResult::Ok(buf)
+        })
+        .unwrap();
+    assert_eq!(v[63], 42);
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Account {
+    id: u64,
+    balance: i64,
+    history: [u64; 4],
+}
+
+// three 8-byte fields, so there is no padding
+unsafe impl NoUninit for Account {}
+
+#[test]
+fn tvar_spanning_stripes() {
+    let stm = STM::new();
+    let a = stm.alloc(Account {
+        id: 1,
+        balance: 100,
+        history: [0; 4],
+    });
+    let b = stm.alloc(7u8);
+    assert_eq!(a.addr(), 0);
+    assert_eq!(b.addr() % stm.stripe_size(), 0);
+    assert!(b.addr() >= std::mem::size_of::<Account>());
+
+    stm.write_transaction(|tr| {
+        let mut acc = tr.read(&a).unwrap();
+        acc.balance -= 30;
+        acc.history[3] = 30;
+        tr.write(&a, acc);
+        tr.write(&b, 8);
+        STMResult::Ok(())
+    })
+    .unwrap();
+
+    let (acc, n) = stm
+        .read_transaction(|tr| STMResult::Ok((tr.read(&a).unwrap(), tr.read(&b).unwrap())))
+        .unwrap();
+    assert_eq!(
+        acc,
+        Account {
+            id: 1,
+            balance: 70,
+            history: [0, 0, 0, 30],
+        }
+    );
+    assert_eq!(n, 8);
+}
+
+#[test]
+fn or_else_falls_back_to_second() {
+    let stm = STM::new();
+    let a = stm.alloc(0u32);
+    let b = stm.alloc(1u32);
+    let log = stm.alloc(0u32);
+
+    let take = |tr: &mut WriteTrans, q| {
+        let n = read!(tr, q);
+        if n == 0 {
+            return STMResult::Retry;
+        }
+        tr.write(q, n - 1);
+        STMResult::Ok(n)
+    };
+
+    let r = stm.write_transaction(|tr| {
+        tr.or_else(
+            |tr| {
+                tr.write(&log, 1);
+                take(tr, &a)
+            },
+            |tr| take(tr, &b),
+        )
+    });
+    assert_eq!(r, Some(1));
+
+    let v = stm
+        .read_transaction(|tr| STMResult::Ok((read!(tr, &a), read!(tr, &b), read!(tr, &log))))
+        .unwrap();
+    assert_eq!(v, (0, 0, 0));
+}
+
+#[test]
+fn max_retries_reports_aborts() {
+    let stm = STM::new().with_contention_manager(MaxRetries {
+        inner: Aggressive,
+        max: 3,
+    });
+    let n = stm.alloc(0u64);
+    let seen = std::sync::Mutex::new(Vec::new());
+
+    let r = stm.try_write_transaction(|tr| {
+        seen.lock().unwrap().push(tr.aborts());
+        let v = read!(tr, &n);
+        stm.write_transaction(|tr| {
+            tr.write(&n, v + 1);
+            STMResult::Ok(())
+        });
+        tr.write(&n, v + 10);
+        STMResult::Ok(())
+    });
+
+    assert_eq!(r, Err(STMError::RetryLimit { aborts: 3 }));
+    assert_eq!(*seen.lock().unwrap(), vec![0, 1, 2]);
+}
+
+// passes every decision of Karma on to the test
+struct RecordKarma(Karma, Arc<std::sync::Mutex<Vec<Resolution>>>);
+
+impl ContentionManager for RecordKarma {
+    fn on_abort(&self, stat: &TransStat, enemy: Option<usize>) -> Resolution {
+        let r = self.0.on_abort(stat, enemy);
+        self.1.lock().unwrap().push(r);
+        r
+    }
+}
+
+// a transaction reading `reads` TVars conflicts with one writing `writes` of
+// them; returns how Karma resolves the conflict for the reader
+fn karma_resolution(reads: usize, writes: usize) -> Resolution {
+    let record = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let stm = STM::new().with_contention_manager(RecordKarma(Karma::default(), record.clone()));
+    let vars: Vec<_> = (0..8).map(|_| stm.alloc(0u64)).collect();
+    let sum = stm.alloc(0u64);
+
+    let first = AtomicBool::new(true);
+    stm.write_transaction(|tr| {
+        let mut s = 0;
+        for v in &vars[..reads] {
+            s += read!(tr, v);
+        }
+        if first.swap(false, Ordering::Relaxed) {
+            stm.write_transaction(|tr| {
+                for v in &vars[..writes] {
+                    tr.write(v, 1);
+                }
+                STMResult::Ok(())
+            });
+        }
+        tr.write(&sum, s);
+        STMResult::Ok(())
+    });
+
+    let v = stm.read_transaction(|tr| STMResult::Ok(read!(tr, &sum)));
+    assert_eq!(v, Some(reads.min(writes) as u64));
+
+    let resolutions = record.lock().unwrap();
+    assert_eq!(resolutions.len(), 1);
+    resolutions[0]
+}
+
+#[test]
+fn karma_lets_higher_karma_win() {
+    // 8 reads and a write against 1 write: the reader waits for its enemy
+    assert_eq!(karma_resolution(8, 1), Resolution::WaitForEnemy);
+    // 1 read and a write against 8 writes: the reader backs off
+    assert_eq!(karma_resolution(1, 8), Resolution::AbortSelf);
+}
+
+#[test]
+fn stats_count_commits_and_aborts() {
+    let stm = STM::new().with_stats();
+    let a = stm.alloc(0u64);
+    let b = stm.alloc(0u64);
+
+    let first = AtomicBool::new(true);
+    stm.write_transaction(|tr| {
+        let v = read!(tr, &b);
+        if first.swap(false, Ordering::Relaxed) {
+            stm.write_transaction(|tr| {
+                tr.write(&b, 1);
+                STMResult::Ok(())
+            });
+        }
+        tr.write(&a, v);
+        STMResult::Ok(())
+    })
+    .unwrap();
+
+    let stats = stm.stats().unwrap();
+    assert_eq!(stats.commits, 4);
+    assert_eq!(stats.validate_aborts, 1);
+    assert_eq!(stats.aborts(), 1);
+    assert_eq!(stats.max_aborts_per_transaction, 1);
+    assert_eq!(stats.hot_stripes, vec![(b.addr(), 1)]);
+
+    assert!(STM::new().stats().is_none());
+}
+
+#[test]
+fn collections_update_atomically() {
+    let stm = STM::with_size(MEM_SIZE, 8);
+    let queue = TQueue::new(&stm, 4);
+    let map = THashMap::new(&stm, 4, 4);
+    let counter = TCounter::new(&stm, 0);
+
+    stm.write_transaction(|tr| {
+        for i in 1..=4u64 {
+            if !opt!(queue.push(tr, i)) {
+                return STMResult::Abort;
+            }
+        }
+        STMResult::Ok(())
+    })
+    .unwrap();
+
+    let full = stm
+        .write_transaction(|tr| STMResult::Ok(opt!(queue.push(tr, 5))))
+        .unwrap();
+    assert!(!full);
+
+    for _ in 0..4 {
+        stm.write_transaction(|tr| {
+            let v = match opt!(queue.pop(tr)) {
+                Some(v) => v,
+                None => return STMResult::Retry,
+            };
+            if !opt!(map.insert(tr, v, v * 10)) {
+                return STMResult::Abort;
+            }
+            opt!(counter.add(tr, 1));
+            STMResult::Ok(())
+        })
+        .unwrap();
+    }
+
+    let (len, n, v3, v5) = stm
+        .read_transaction(|tr| {
+            STMResult::Ok((
+                opt!(queue.len(tr)),
+                opt!(counter.get(tr)),
+                opt!(map.get(tr, &3)),
+                opt!(map.get(tr, &5)),
+            ))
+        })
+        .unwrap();
+    assert_eq!((len, n, v3, v5), (0, 4, Some(30), None));
+
+    let removed = stm
+        .write_transaction(|tr| STMResult::Ok(opt!(map.remove(tr, &3))))
+        .unwrap();
+    assert_eq!(removed, Some(30));
+}
+
+#[test]
+fn nested_abort_discards_inner_writes() {
+    let stm = STM::new();
+    let a = stm.alloc(0u32);
+    let b = stm.alloc(0u32);
+
+    let withdraw = |tr: &mut WriteTrans, amount| {
+        tr.write(&b, amount);
+        let v = read!(tr, &a);
+        if v < amount {
+            return STMResult::Abort;
+        }
+        tr.write(&a, v - amount);
+        STMResult::Ok(())
+    };
+
+    let ok = stm
+        .write_transaction(|tr| {
+            tr.write(&a, 5);
+            let ok = matches!(tr.nested(|tr| withdraw(tr, 10)), STMResult::Ok(()));
+            STMResult::Ok(ok)
+        })
+        .unwrap();
+    assert!(!ok);
+
+    let v = stm
+        .read_transaction(|tr| STMResult::Ok((read!(tr, &a), read!(tr, &b))))
+        .unwrap();
+    assert_eq!(v, (5, 0));
+
+    let ok = stm
+        .write_transaction(|tr| {
+            let ok = matches!(tr.nested(|tr| withdraw(tr, 3)), STMResult::Ok(()));
+            STMResult::Ok(ok)
+        })
+        .unwrap();
+    assert!(ok);
+
+    let v = stm
+        .read_transaction(|tr| STMResult::Ok((read!(tr, &a), read!(tr, &b))))
+        .unwrap();
+    assert_eq!(v, (2, 3));
+}
+
+#[test]
+fn hooks_run_once_after_outcome() {
+    const NUM_THREADS: usize = 4;
+    const NUM_LOOP: usize = if cfg!(miri) { 10 } else { 200 };
+
+    let stm = Arc::new(STM::new());
+    let n = stm.alloc(0u64);
+    let commits = Arc::new(AtomicUsize::new(0));
+    let aborts = Arc::new(AtomicUsize::new(0));
+
+    let v: Vec<_> = (0..NUM_THREADS)
+        .map(|_| {
+            let stm0 = stm.clone();
+            let commits0 = commits.clone();
+            let aborts0 = aborts.clone();
+            thread::spawn(move || {
+                for _ in 0..NUM_LOOP {
+                    stm0.write_transaction(|tr| {
+                        let v = read!(tr, &n);
+                        tr.write(&n, v + 1);
+                        let c = commits0.clone();
+                        tr.on_commit(move || {
+                            c.fetch_add(1, Ordering::Relaxed);
+                        });
+                        let a = aborts0.clone();
+                        tr.on_abort(move || {
+                            a.fetch_add(1, Ordering::Relaxed);
+                        });
+                        STMResult::Ok(())
+                    })
+                    .unwrap();
+                }
+            })
+        })
+        .collect();
+
+    for t in v {
+        t.join().unwrap();
+    }
+
+    assert_eq!(commits.load(Ordering::Relaxed), NUM_THREADS * NUM_LOOP);
+    assert_eq!(aborts.load(Ordering::Relaxed), 0);
+
+    let r = stm.try_write_transaction(|tr| {
+        let c = commits.clone();
+        tr.on_commit(move || {
+            c.fetch_add(1, Ordering::Relaxed);
+        });
+        let a = aborts.clone();
+        tr.on_abort(move || {
+            a.fetch_add(1, Ordering::Relaxed);
+        });
+        STMResult::<()>::Abort
+    });
+    assert_eq!(r, Err(STMError::Abort));
+    assert_eq!(commits.load(Ordering::Relaxed), NUM_THREADS * NUM_LOOP);
+    assert_eq!(aborts.load(Ordering::Relaxed), 1);
+
+    // hooks registered inside an aborted nested transaction are discarded
+    stm.write_transaction(|tr| {
+        let _ = tr.nested(|tr| {
+            let a = aborts.clone();
+            tr.on_abort(move || {
+                a.fetch_add(1, Ordering::Relaxed);
+            });
+            STMResult::<()>::Abort
+        });
+        STMResult::Ok(())
+    })
+    .unwrap();
+    assert_eq!(aborts.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn irrevocable_commits_on_first_attempt() {
+    const NUM_THREADS: usize = 4;
+    const NUM_LOOP: u64 = if cfg!(miri) { 10 } else { 200 };
+
+    let stm = Arc::new(STM::new());
+    let n = stm.alloc(0u64);
+
+    let v: Vec<_> = (0..NUM_THREADS)
+        .map(|_| {
+            let stm0 = stm.clone();
+            thread::spawn(move || {
+                for _ in 0..NUM_LOOP {
+                    stm0.write_transaction(|tr| {
+                        let v = read!(tr, &n);
+                        tr.write(&n, v + 1);
+                        STMResult::Ok(())
+                    })
+                    .unwrap();
+                }
+            })
+        })
+        .collect();
+
+    let mut attempts = 0;
+    for _ in 0..10 {
+        stm.irrevocable(|tr| {
+            attempts += 1;
+            let v = read!(tr, &n);
+            tr.write(&n, v + 1);
+            STMResult::Ok(())
+        })
+        .unwrap();
+    }
+    assert_eq!(attempts, 10);
+
+    for t in v {
+        t.join().unwrap();
+    }
+
+    let v = stm
+        .read_transaction(|tr| STMResult::Ok(read!(tr, &n)))
+        .unwrap();
+    assert_eq!(v, NUM_LOOP * NUM_THREADS as u64 + 10);
+
+    let r = stm.irrevocable(|_| STMResult::<()>::Retry);
+    assert_eq!(r, Err(STMError::Retry));
+
+    // a write transaction inside an irrevocable one panics instead of
+    // deadlocking, and the STM is still usable afterwards
+    let r = panic::catch_unwind(AssertUnwindSafe(|| {
+        stm.irrevocable(|_| {
+            stm.alloc(0u64);
+            STMResult::Ok(())
+        })
+    }));
+    assert!(r.is_err());
+    stm.write_transaction(|tr| {
+        tr.write(&n, 0);
+        STMResult::Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn transfers_in_opposite_orders() {
+    const NUM_THREADS: usize = 4;
+    const NUM_ACCOUNTS: usize = 8;
+    const NUM_LOOP: usize = if cfg!(miri) { 10 } else { 300 };
+
+    let stm = Arc::new(STM::new());
+    let accounts: Arc<Vec<_>> = Arc::new((0..NUM_ACCOUNTS).map(|_| stm.alloc(100i64)).collect());
+
+    let v: Vec<_> = (0..NUM_THREADS)
+        .map(|i| {
+            let stm0 = stm.clone();
+            let accounts0 = accounts.clone();
+            thread::spawn(move || {
+                for j in 0..NUM_LOOP {
+                    let (from, to) = if i % 2 == 0 {
+                        (j % NUM_ACCOUNTS, (j + 1) % NUM_ACCOUNTS)
+                    } else {
+                        ((j + 1) % NUM_ACCOUNTS, j % NUM_ACCOUNTS)
+                    };
+                    let (a, b) = (&accounts0[from], &accounts0[to]);
+
+                    stm0.write_transaction(|tr| {
+                        let x = read!(tr, a);
+                        tr.write(a, x - 1);
+                        let y = read!(tr, b);
+                        tr.write(b, y + 1);
+                        // read back from the write set
+                        assert_eq!(read!(tr, a), x - 1);
+                        STMResult::Ok(())
+                    })
+                    .unwrap();
+
+                    // a write transaction without writes commits read-only
+                    let sum = stm0
+                        .write_transaction(|tr| {
+                            let mut sum = 0;
+                            for acc in accounts0.iter() {
+                                sum += read!(tr, acc);
+                            }
+                            STMResult::Ok(sum)
+                        })
+                        .unwrap();
+                    assert_eq!(sum, 100 * NUM_ACCOUNTS as i64);
+                }
+            })
+        })
+        .collect();
+
+    for t in v {
+        t.join().unwrap();
+    }
+}
+
+#[test]
+// the executor builds its wakers with futures' waker_ref, which Miri rejects
+#[cfg_attr(miri, ignore)]
+fn atomically_parks_task_on_retry() {
+    const NUM_ITEMS: u32 = 10;
+
+    let stm = Arc::new(STM::new());
+    let slot = stm.alloc([0u32; 2]);
+    let (tx, rx) = mpsc::channel();
+
+    // both tasks share one executor thread, so the consumer must not block it
+    let executor = Executor::new();
+    let spawner = executor.get_spawner();
+
+    let stm0 = stm.clone();
+    spawner.spawn(async move {
+        for _ in 0..NUM_ITEMS {
+            let v = stm0
+                .atomically(|tr| {
+                    let [len, v] = read!(tr, &slot);
+                    if len == 0 {
+                        return STMResult::Retry;
+                    }
+                    tr.write(&slot, [0, 0]);
+                    STMResult::Ok(v)
+                })
+                .await
+                .unwrap();
+            tx.send(v).unwrap();
+        }
+    });
+
+    let stm0 = stm.clone();
+    spawner.spawn(async move {
+        for i in 0..NUM_ITEMS {
+            stm0.atomically(|tr| {
+                let [len, _] = read!(tr, &slot);
+                if len != 0 {
+                    return STMResult::Retry;
+                }
+                tr.write(&slot, [1, i]);
+                STMResult::Ok(())
+            })
+            .await
+            .unwrap();
+        }
+    });
+
+    thread::spawn(move || executor.run());
+
+    for i in 0..NUM_ITEMS {
+        assert_eq!(rx.recv_timeout(Duration::from_secs(10)), Ok(i));
+    }
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn atomically_asks_contention_manager() {
+    let stm = STM::new().with_contention_manager(MaxRetries {
+        inner: Aggressive,
+        max: 1,
+    });
+    let n = stm.alloc(0u64);
+
+    let r = futures::executor::block_on(stm.atomically(|tr| {
+        let v = read!(tr, &n);
+        stm.write_transaction(|tr| {
+            tr.write(&n, v + 1);
+            STMResult::Ok(())
+        });
+        tr.write(&n, v + 10);
+        STMResult::Ok(())
+    }));
+
+    assert_eq!(r, Err(STMError::RetryLimit { aborts: 1 }));
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn atomically_parks_task_during_irrevocable() {
+    let stm = Arc::new(STM::new());
+    let n = stm.alloc(0u64);
+    let (tx, rx) = mpsc::channel();
+
+    let executor = Executor::new();
+    let spawner = executor.get_spawner();
+    thread::spawn(move || executor.run());
+
+    stm.irrevocable(|tr| {
+        let stm0 = stm.clone();
+        let tx0 = tx.clone();
+        spawner.spawn(async move {
+            stm0.atomically(|tr| {
+                let v = read!(tr, &n);
+                tr.write(&n, v + 1);
+                STMResult::Ok(())
+            })
+            .await
+            .unwrap();
+            tx0.send("committed").unwrap();
+        });
+
+        // runs on the same executor thread after the first task meets the
+        // irrevocable transaction, so it only gets there if that one parked
+        let tx0 = tx.clone();
+        spawner.spawn(async move {
+            tx0.send("yielded").unwrap();
+        });
+        assert_eq!(rx.recv_timeout(Duration::from_secs(10)), Ok("yielded"));
+
+        let v = read!(tr, &n);
+        tr.write(&n, v + 10);
+        STMResult::Ok(())
+    })
+    .unwrap();
+
+    assert_eq!(rx.recv_timeout(Duration::from_secs(10)), Ok("committed"));
+    let v = stm.read_transaction(|tr| STMResult::Ok(read!(tr, &n)));
+    assert_eq!(v, Some(11));
+}